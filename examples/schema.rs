@@ -3,7 +3,8 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use blazarbit_protocol::msg::{ExecuteMsg, InstantiateMsg};
+use blazarbit_protocol::msg::{DcasResponse, DelegationsResponse, ExecuteMsg, InstantiateMsg, LimitOrdersResponse, MigrateMsg, PipelinesResponse, QueryMsg, ScheduledPipelinesResponse, SimulationResponse};
+use blazarbit_protocol::state::{Config, Lock, PauseFlags, PipelineRecord, Receipt, Unbonding};
 
 fn main() {
 
@@ -14,4 +15,18 @@ fn main() {
 
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
+    export_schema(&schema_for!(Config), &out_dir);
+    export_schema(&schema_for!(PauseFlags), &out_dir);
+    export_schema(&schema_for!(SimulationResponse), &out_dir);
+    export_schema(&schema_for!(PipelineRecord), &out_dir);
+    export_schema(&schema_for!(PipelinesResponse), &out_dir);
+    export_schema(&schema_for!(ScheduledPipelinesResponse), &out_dir);
+    export_schema(&schema_for!(LimitOrdersResponse), &out_dir);
+    export_schema(&schema_for!(DcasResponse), &out_dir);
+    export_schema(&schema_for!(Lock), &out_dir);
+    export_schema(&schema_for!(DelegationsResponse), &out_dir);
+    export_schema(&schema_for!(Unbonding), &out_dir);
+    export_schema(&schema_for!(Receipt), &out_dir);
 }
@@ -0,0 +1,350 @@
+use cosmwasm_std::{Addr, Api, Deps, DepsMut, MessageInfo, Response, StdResult, Storage};
+
+use crate::msg::{ExecuteMsg, OptionalSetting};
+use crate::state::{CommandFee, Config, Fee, PauseFlags, ReferralMode, CONFIG, PAUSED, PENDING_OWNER};
+use crate::ContractError;
+
+const MAX_BPS: u16 = 10_000;
+/// Deepest nesting of hops the admin may allow
+pub const MAX_DEPTH_LIMIT: u32 = 8;
+/// Packet timeouts range from a minute to a week
+pub const MIN_TIMEOUT: u64 = 60;
+pub const MAX_TIMEOUT: u64 = 7 * 24 * 60 * 60;
+
+pub fn assert_admin(deps: Deps, sender: &Addr) -> Result<Config, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if config.admin != *sender {
+        return Err(ContractError::NotAdmin {});
+    }
+    Ok(config)
+}
+
+pub fn validate_fee(fee: &Fee) -> Result<(), ContractError> {
+    match fee {
+        Fee::Bps(bps) if *bps > MAX_BPS => Err(ContractError::InvalidFee {
+            reason: format!("{} bps is more than the whole amount", bps),
+        }),
+        Fee::Flat(asset) if asset.amount.is_zero() => Err(ContractError::InvalidFee {
+            reason: "flat fee must be greater than zero".to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// A zero limit would refuse every pipeline
+pub fn validate_limit(field: &str, limit: u64) -> Result<(), ContractError> {
+    if limit == 0 {
+        return Err(ContractError::ZeroAmount { field: field.to_string() });
+    }
+    Ok(())
+}
+
+pub fn validate_range(field: &str, value: u64, min: u64, max: u64) -> Result<(), ContractError> {
+    if value < min || value > max {
+        return Err(ContractError::OutOfRange {
+            field: field.to_string(),
+            min,
+            max,
+        });
+    }
+    Ok(())
+}
+
+pub fn validate_timeout(field: &str, timeout: u64) -> Result<(), ContractError> {
+    validate_range(field, timeout, MIN_TIMEOUT, MAX_TIMEOUT)
+}
+
+pub fn validate_max_depth(max_depth: u32) -> Result<(), ContractError> {
+    validate_range("max_depth", max_depth as u64, 1, MAX_DEPTH_LIMIT as u64)
+}
+
+pub fn validate_addresses(api: &dyn Api, addresses: &[String]) -> StdResult<Vec<Addr>> {
+    addresses
+        .iter()
+        .map(|addr| api.addr_validate(addr))
+        .collect()
+}
+
+/// Fields of `ExecuteMsg::UpdateConfig`, empty ones are kept as they are unless `clear`
+/// lists them
+#[derive(Default)]
+pub struct ConfigUpdate {
+    pub ibc_transfer_timeout: Option<u64>,
+    pub ibc_hop_timeout: Option<u64>,
+    pub fee: Option<Fee>,
+    pub command_fees: Option<Vec<CommandFee>>,
+    pub allowed_targets: Option<Vec<String>>,
+    pub cw20_ics20_contract: Option<String>,
+    pub max_referral_bps: Option<u16>,
+    pub referral_mode: Option<ReferralMode>,
+    pub max_depth: Option<u32>,
+    pub max_commands: Option<u32>,
+    pub max_packet_size: Option<u64>,
+    pub keeper_tip: Option<Fee>,
+    pub unbonding_period: Option<u64>,
+    pub clear: Vec<OptionalSetting>,
+}
+
+pub fn update_config(deps: DepsMut, info: MessageInfo, update: ConfigUpdate) -> Result<Response, ContractError> {
+    let ConfigUpdate {
+        ibc_transfer_timeout,
+        ibc_hop_timeout,
+        fee,
+        command_fees,
+        allowed_targets,
+        cw20_ics20_contract,
+        max_referral_bps,
+        referral_mode,
+        max_depth,
+        max_commands,
+        max_packet_size,
+        keeper_tip,
+        unbonding_period,
+        clear,
+    } = update;
+    let mut config = assert_admin(deps.as_ref(), &info.sender)?;
+
+    for setting in clear {
+        let (field, set) = match setting {
+            OptionalSetting::Fee => ("fee", fee.is_some()),
+            OptionalSetting::KeeperTip => ("keeper_tip", keeper_tip.is_some()),
+            OptionalSetting::Cw20Ics20Contract => ("cw20_ics20_contract", cw20_ics20_contract.is_some()),
+        };
+        if set {
+            return Err(ContractError::SetAndCleared { field: field.to_string() });
+        }
+        match setting {
+            OptionalSetting::Fee => config.fee = None,
+            OptionalSetting::KeeperTip => config.keeper_tip = None,
+            OptionalSetting::Cw20Ics20Contract => config.cw20_ics20_contract = None,
+        }
+    }
+    if let Some(timeout) = ibc_transfer_timeout {
+        validate_timeout("ibc_transfer_timeout", timeout)?;
+        config.ibc_transfer_timeout = timeout;
+    }
+    if let Some(timeout) = ibc_hop_timeout {
+        validate_timeout("ibc_hop_timeout", timeout)?;
+        config.ibc_hop_timeout = timeout;
+    }
+    if let Some(fee) = fee {
+        validate_fee(&fee)?;
+        config.fee = Some(fee);
+    }
+    if let Some(command_fees) = command_fees {
+        for command_fee in &command_fees {
+            validate_fee(&command_fee.fee)?;
+        }
+        config.command_fees = command_fees;
+    }
+    if let Some(targets) = allowed_targets {
+        config.allowed_targets = validate_addresses(deps.api, &targets)?;
+    }
+    if let Some(ics20_contract) = cw20_ics20_contract {
+        config.cw20_ics20_contract = Some(deps.api.addr_validate(&ics20_contract)?);
+    }
+    if let Some(max_bps) = max_referral_bps {
+        validate_fee(&Fee::Bps(max_bps))?;
+        config.max_referral_bps = max_bps;
+    }
+    if let Some(mode) = referral_mode {
+        config.referral_mode = mode;
+    }
+    if let Some(max_depth) = max_depth {
+        validate_max_depth(max_depth)?;
+        config.max_depth = max_depth;
+    }
+    if let Some(max_commands) = max_commands {
+        validate_limit("max_commands", max_commands as u64)?;
+        config.max_commands = max_commands;
+    }
+    if let Some(max_packet_size) = max_packet_size {
+        validate_limit("max_packet_size", max_packet_size)?;
+        config.max_packet_size = max_packet_size;
+    }
+    if let Some(tip) = keeper_tip {
+        validate_fee(&tip)?;
+        config.keeper_tip = Some(tip);
+    }
+    if let Some(unbonding_period) = unbonding_period {
+        validate_limit("unbonding_period", unbonding_period)?;
+        config.unbonding_period = unbonding_period;
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("method", "update_config"))
+}
+
+/// Entries of `ExecuteMsg::UpdateAllowlist`
+#[derive(Default)]
+pub struct AllowlistUpdate {
+    pub add_targets: Vec<String>,
+    pub remove_targets: Vec<String>,
+    pub add_channels: Vec<String>,
+    pub remove_channels: Vec<String>,
+    pub add_pools: Vec<u64>,
+    pub remove_pools: Vec<u64>,
+    pub add_contracts: Vec<String>,
+    pub remove_contracts: Vec<String>,
+    pub add_type_urls: Vec<String>,
+    pub remove_type_urls: Vec<String>,
+}
+
+pub fn update_allowlist(deps: DepsMut, info: MessageInfo, update: AllowlistUpdate) -> Result<Response, ContractError> {
+    let mut config = assert_admin(deps.as_ref(), &info.sender)?;
+    let AllowlistUpdate {
+        add_targets,
+        remove_targets,
+        add_channels,
+        remove_channels,
+        add_pools,
+        remove_pools,
+        add_contracts,
+        remove_contracts,
+        add_type_urls,
+        remove_type_urls,
+    } = update;
+
+    let remove_targets = validate_addresses(deps.api, &remove_targets)?;
+    config.allowed_targets.retain(|target| !remove_targets.contains(target));
+    for target in validate_addresses(deps.api, &add_targets)? {
+        if !config.allowed_targets.contains(&target) {
+            config.allowed_targets.push(target);
+        }
+    }
+
+    config.allowed_channels.retain(|channel| !remove_channels.contains(channel));
+    for channel in add_channels {
+        if !config.allowed_channels.contains(&channel) {
+            config.allowed_channels.push(channel);
+        }
+    }
+
+    config.allowed_pools.retain(|pool_id| !remove_pools.contains(pool_id));
+    for pool_id in add_pools {
+        if !config.allowed_pools.contains(&pool_id) {
+            config.allowed_pools.push(pool_id);
+        }
+    }
+
+    let remove_contracts = validate_addresses(deps.api, &remove_contracts)?;
+    config.allowed_contracts.retain(|contract| !remove_contracts.contains(contract));
+    for contract in validate_addresses(deps.api, &add_contracts)? {
+        if !config.allowed_contracts.contains(&contract) {
+            config.allowed_contracts.push(contract);
+        }
+    }
+
+    config.allowed_type_urls.retain(|type_url| !remove_type_urls.contains(type_url));
+    for type_url in add_type_urls {
+        if !config.allowed_type_urls.contains(&type_url) {
+            config.allowed_type_urls.push(type_url);
+        }
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("method", "update_allowlist"))
+}
+
+pub fn propose_new_owner(deps: DepsMut, info: MessageInfo, owner: String) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info.sender)?;
+
+    let owner = deps.api.addr_validate(&owner)?;
+    PENDING_OWNER.save(deps.storage, &owner)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "propose_new_owner")
+        .add_attribute("owner", owner))
+}
+
+pub fn accept_ownership(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    match PENDING_OWNER.may_load(deps.storage)? {
+        Some(owner) if owner == info.sender => {}
+        _ => return Err(ContractError::Unauthorized {}),
+    }
+
+    let mut config = CONFIG.load(deps.storage)?;
+    config.admin = info.sender;
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_OWNER.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("method", "accept_ownership")
+        .add_attribute("admin", config.admin))
+}
+
+/// Entries of `ExecuteMsg::SetPause`, `None` keeps the flag as it is
+#[derive(Default)]
+pub struct PauseUpdate {
+    pub transfers: Option<bool>,
+    pub swaps: Option<bool>,
+    pub nft: Option<bool>,
+    pub hops: Option<bool>,
+    pub ibc_receive: Option<bool>,
+    pub staking: Option<bool>,
+    pub governance: Option<bool>,
+}
+
+pub fn set_pause(deps: DepsMut, info: MessageInfo, update: PauseUpdate) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info.sender)?;
+
+    let mut flags = PAUSED.may_load(deps.storage)?.unwrap_or_default();
+    flags.transfers = update.transfers.unwrap_or(flags.transfers);
+    flags.swaps = update.swaps.unwrap_or(flags.swaps);
+    flags.nft = update.nft.unwrap_or(flags.nft);
+    flags.hops = update.hops.unwrap_or(flags.hops);
+    flags.ibc_receive = update.ibc_receive.unwrap_or(flags.ibc_receive);
+    flags.staking = update.staking.unwrap_or(flags.staking);
+    flags.governance = update.governance.unwrap_or(flags.governance);
+    PAUSED.save(deps.storage, &flags)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "set_pause")
+        .add_attribute("transfers", flags.transfers.to_string())
+        .add_attribute("swaps", flags.swaps.to_string())
+        .add_attribute("nft", flags.nft.to_string())
+        .add_attribute("hops", flags.hops.to_string())
+        .add_attribute("ibc_receive", flags.ibc_receive.to_string())
+        .add_attribute("staking", flags.staking.to_string())
+        .add_attribute("governance", flags.governance.to_string()))
+}
+
+pub fn pause_flags(storage: &dyn Storage) -> StdResult<PauseFlags> {
+    Ok(PAUSED.may_load(storage)?.unwrap_or_default())
+}
+
+/// Fails if the capability the command belongs to is paused
+pub fn assert_not_paused(storage: &dyn Storage, msg: &ExecuteMsg) -> Result<(), ContractError> {
+    let flags = pause_flags(storage)?;
+    let capability = match msg {
+        ExecuteMsg::Transfer { .. } | ExecuteMsg::IbcTransfer { .. } if flags.transfers => "transfers",
+        ExecuteMsg::Swap { .. }
+        | ExecuteMsg::JoinPool { .. }
+        | ExecuteMsg::ExitPool { .. }
+        | ExecuteMsg::LockTokens { .. }
+        | ExecuteMsg::SuperfluidDelegate { .. }
+        | ExecuteMsg::Unlock { .. }
+            if flags.swaps => "swaps",
+        ExecuteMsg::PurchaseNFT { .. } if flags.nft => "NFT purchases",
+        ExecuteMsg::ContractHop { .. }
+        | ExecuteMsg::IbcContractHop { .. }
+        | ExecuteMsg::WasmExecute { .. }
+        | ExecuteMsg::StargateMsg { .. }
+            if flags.hops => "hops",
+        ExecuteMsg::Delegate { .. }
+        | ExecuteMsg::Undelegate { .. }
+        | ExecuteMsg::Redelegate { .. }
+        | ExecuteMsg::ClaimRewards { .. }
+            if flags.staking => "staking commands",
+        ExecuteMsg::Vote { .. } | ExecuteMsg::VoteWeighted { .. } if flags.governance => "governance votes",
+        _ => return Ok(()),
+    };
+    Err(ContractError::Paused { capability: capability.to_string() })
+}
+
+pub fn assert_ibc_receive_not_paused(storage: &dyn Storage) -> Result<(), ContractError> {
+    if pause_flags(storage)?.ibc_receive {
+        return Err(ContractError::Paused { capability: "inbound IBC packets".to_string() });
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+
+use cosmwasm_std::{to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, StdResult, Uint128, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ContractError;
+
+/// Kind of token an `Asset` is made of
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    /// Native bank denom, e.g. `uosmo` or an `ibc/...` voucher
+    Native(String),
+    /// CW20 token contract
+    Cw20(Addr),
+}
+
+impl AssetInfo {
+    pub fn is_native(&self) -> bool {
+        matches!(self, AssetInfo::Native(_))
+    }
+}
+
+impl fmt::Display for AssetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetInfo::Native(denom) => write!(f, "{}", denom),
+            AssetInfo::Cw20(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+/// Amount of a native or CW20 token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+impl Asset {
+    pub fn native(denom: impl Into<String>, amount: impl Into<Uint128>) -> Self {
+        Asset {
+            info: AssetInfo::Native(denom.into()),
+            amount: amount.into(),
+        }
+    }
+
+    pub fn cw20(contract_addr: Addr, amount: impl Into<Uint128>) -> Self {
+        Asset {
+            info: AssetInfo::Cw20(contract_addr),
+            amount: amount.into(),
+        }
+    }
+
+    /// Returns the asset as a bank coin, fails for CW20 tokens
+    pub fn to_coin(&self) -> Result<Coin, ContractError> {
+        match &self.info {
+            AssetInfo::Native(denom) => Ok(Coin {
+                denom: denom.clone(),
+                amount: self.amount,
+            }),
+            AssetInfo::Cw20(_) => Err(ContractError::UnsupportedCw20Command {}),
+        }
+    }
+
+    /// Bank send or CW20 transfer of this asset to `recipient`
+    pub fn transfer_msg(&self, recipient: &str) -> StdResult<CosmosMsg> {
+        Ok(match &self.info {
+            AssetInfo::Native(denom) => BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount: self.amount,
+                }],
+            }
+            .into(),
+            AssetInfo::Cw20(contract_addr) => WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: self.amount,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+        })
+    }
+
+    /// Calls `contract` with `msg` paying with this asset: native coins are attached
+    /// as funds, CW20 tokens go through `Send` with `msg` as the hook
+    pub fn execute_msg(&self, contract: &str, msg: Binary) -> StdResult<CosmosMsg> {
+        Ok(match &self.info {
+            AssetInfo::Native(denom) => WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg,
+                funds: vec![Coin {
+                    denom: denom.clone(),
+                    amount: self.amount,
+                }],
+            }
+            .into(),
+            AssetInfo::Cw20(contract_addr) => WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: contract.to_string(),
+                    amount: self.amount,
+                    msg,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+        })
+    }
+
+    /// Checks that exactly this asset was received with the message
+    pub fn assert_received(&self, received: &[Asset]) -> Result<(), ContractError> {
+        match received {
+            [asset] if asset == self => Ok(()),
+            _ => Err(ContractError::FundsMismatch {
+                expected: self.to_string(),
+                received: join_assets(received),
+            }),
+        }
+    }
+}
+
+impl From<Coin> for Asset {
+    fn from(coin: Coin) -> Self {
+        Asset::native(coin.denom, coin.amount)
+    }
+}
+
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.amount, self.info)
+    }
+}
+
+/// Calls `contract` with `msg` paying with `assets`. Native coins can be attached
+/// together, a CW20 token has to be sent alone.
+pub fn execute_msg_with_assets(contract: &str, msg: Binary, assets: &[Asset]) -> Result<CosmosMsg, ContractError> {
+    if let [asset] = assets {
+        return Ok(asset.execute_msg(contract, msg)?);
+    }
+
+    let funds = assets
+        .iter()
+        .map(Asset::to_coin)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(WasmMsg::Execute {
+        contract_addr: contract.to_string(),
+        msg,
+        funds,
+    }
+    .into())
+}
+
+pub fn join_assets(assets: &[Asset]) -> String {
+    assets
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// `funds` without `assets`, fails when they do not cover them
+pub fn deduct(funds: &[Asset], assets: &[Asset]) -> Result<Vec<Asset>, ContractError> {
+    let mut funds = funds.to_vec();
+    for asset in assets {
+        let owned = funds
+            .iter_mut()
+            .find(|owned| owned.info == asset.info && owned.amount >= asset.amount)
+            .ok_or_else(|| ContractError::InsufficientFunds { funds: asset.to_string() })?;
+        owned.amount -= asset.amount;
+    }
+    funds.retain(|asset| !asset.amount.is_zero());
+    Ok(funds)
+}
+
+/// Checks that a command which spends nothing was not sent anything
+pub fn assert_no_funds(received: &[Asset]) -> Result<(), ContractError> {
+    if received.is_empty() {
+        return Ok(());
+    }
+    Err(ContractError::FundsMismatch {
+        expected: "no funds".to_string(),
+        received: join_assets(received),
+    })
+}
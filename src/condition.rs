@@ -0,0 +1,34 @@
+use cosmwasm_std::{Deps, StdResult};
+
+use crate::asset::Asset;
+use crate::msg::{Condition, ExecuteMsg};
+use crate::osmosis::spot_price;
+
+/// Whether `condition` holds for a pipeline owning `funds`
+pub fn evaluate(deps: Deps, condition: &Condition, funds: &[Asset], previous_step_failed: bool) -> StdResult<bool> {
+    let owned = |asset: &Asset| {
+        funds
+            .iter()
+            .find(|owned| owned.info == asset.info)
+            .map(|owned| owned.amount)
+            .unwrap_or_default()
+    };
+
+    Ok(match condition {
+        Condition::BalanceAtLeast(asset) => owned(asset) >= asset.amount,
+        Condition::BalanceBelow(asset) => owned(asset) < asset.amount,
+        Condition::PriceAbove { pool_id, token_in_denom, token_out_denom, price } => {
+            spot_price(deps, *pool_id, token_in_denom, token_out_denom)? > *price
+        }
+        Condition::PriceBelow { pool_id, token_in_denom, token_out_denom, price } => {
+            spot_price(deps, *pool_id, token_in_denom, token_out_denom)? < *price
+        }
+        Condition::PreviousStepSucceeded {} => !previous_step_failed,
+        Condition::PreviousStepFailed {} => previous_step_failed,
+    })
+}
+
+/// Whether the command running before `next` may fail without failing the pipeline
+pub fn tolerates_failure(next: Option<&ExecuteMsg>) -> bool {
+    matches!(next, Some(ExecuteMsg::If { condition, .. }) if condition.checks_previous_step())
+}
@@ -1,137 +1,626 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{to_binary, DepsMut, Env, MessageInfo, Response, BankMsg, StdError, IbcMsg, SubMsg, SubMsgResult, WasmMsg, Reply, IbcTimeout};
-use cw2::set_contract_version;
-use cw_osmo_proto::osmosis::gamm::v1beta1::{ MsgSwapExactAmountIn, SwapAmountInRoute as Osmo_SwapAmountInRoute };
+use cosmwasm_std::{to_binary, from_binary, Addr, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, IbcMsg, WasmMsg, Reply, IbcTimeout, Storage, Uint128, Attribute};
+use cw2::{get_contract_version, set_contract_version};
+use cw20_ics20::msg::TransferMsg;
+use cw_osmo_proto::osmosis::gamm::v1beta1::{ MsgExitPool, MsgJoinPool, MsgJoinSwapExternAmountIn, MsgSwapExactAmountIn, SwapAmountInRoute as Osmo_SwapAmountInRoute };
 use cw_osmo_proto::cosmos::base::v1beta1::{ Coin as Osmo_Coin };
 use cw_osmo_proto::proto_ext::MessageExt;
+use semver::Version;
 use cw721_base::{
     msg::ExecuteMsg as Cw721ExecuteMsg, Extension,
     MintMsg,
 };
 
+use crate::admin::{
+    accept_ownership, assert_not_paused, pause_flags, propose_new_owner, set_pause, update_config,
+    update_allowlist, validate_addresses, validate_fee, validate_limit, validate_max_depth, validate_timeout,
+    AllowlistUpdate, ConfigUpdate, PauseUpdate,
+};
+use crate::asset::{assert_no_funds, deduct, execute_msg_with_assets, join_assets, Asset, AssetInfo};
+use crate::dca::{cancel_dca, create_dca, dcas, execute_dca, DcaTerms};
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, IbcExecuteMsg, InstantiateMsg};
-use crate::state::{COMMANDS_STACK, CONTRACT_ADDRESS};
+use crate::events::{asset_attributes, command_event, execution_attributes};
+use crate::fees::{accrued_fees, charge_fee, claim_referral_fees, referral_balance, withdraw_fees};
+use crate::osmosis::{proto_coin, share_denom, stargate_msg, EXIT_POOL_TYPE_URL, JOIN_POOL_TYPE_URL};
+use crate::pipeline::{hop_reply, resolve_caller, resolve_cw20_caller, start_pipeline, Caller, HOP_REPLY_ID};
+use crate::limit_order::{cancel_limit_order, fill_limit_order, limit_orders, place_limit_order, OrderTerms};
+use crate::lockup::{claim_unlocked, execute_lock_tokens, execute_superfluid_delegate, execute_unlock, lock_reply, LOCK_REPLY_ID};
+use crate::incoming::settle_incoming;
+use crate::history::{packet_sender, running_step, pipelines_by_sender};
+use crate::gov::execute_vote;
+use crate::msg::{CommandKind, DcasResponse, DelegationsResponse, ExecuteMsg, IbcExecuteMsg, InstantiateMsg, LimitOrdersResponse, MigrateMsg, PipelinesResponse, QueryMsg, ScheduledPipelinesResponse, WeightedVoteOption};
+use crate::receipt::{mint_receipt, redeem_receipt};
+use crate::remote::{deposit_remote, remote_balance, withdraw_remote};
+use crate::schedule::{cancel_scheduled, execute_scheduled, schedule_pipeline, scheduled_pipelines};
+use crate::simulation::simulate_pipeline;
+use crate::staking::{claim_unbonded, delegations, execute_claim_rewards, execute_delegate, execute_redelegate, execute_undelegate};
+use crate::validation::{validate_command, validate_packet_size};
+use crate::state::{Config, Fee, ReferralMode, COMMANDS_STACK, CONFIG, CONTRACT_ADDRESS, DCAS, LIMIT_ORDERS, LOCKS, PENDING_OWNER, PIPELINES, PIPELINE_HISTORY, RECEIPTS, SCHEDULED, UNBONDINGS};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:blazarbit-protocol";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 /// packets live one hour
 pub const PACKET_LIFETIME: u64 = 60 * 60;
+/// contract hop packets live five minutes
+pub const HOP_PACKET_LIFETIME: u64 = 5 * 60;
+/// hops nested in a command by default
+pub const DEFAULT_MAX_DEPTH: u32 = 3;
+/// commands a pipeline may hold by default
+pub const DEFAULT_MAX_COMMANDS: u32 = 16;
+/// contract hop packets are at most 16 KiB by default
+pub const DEFAULT_MAX_PACKET_SIZE: u64 = 16 * 1024;
+/// stake unbonds in 21 days by default, as on the Cosmos Hub
+pub const DEFAULT_UNBONDING_PERIOD: u64 = 21 * 24 * 60 * 60;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    _msg: InstantiateMsg,
+    info: MessageInfo,
+    msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
+    if let Some(fee) = &msg.fee {
+        validate_fee(fee)?;
+    }
+    for command_fee in &msg.command_fees {
+        validate_fee(&command_fee.fee)?;
+    }
+    validate_fee(&Fee::Bps(msg.max_referral_bps))?;
+    let max_commands = msg.max_commands.unwrap_or(DEFAULT_MAX_COMMANDS);
+    validate_limit("max_commands", max_commands as u64)?;
+    let max_packet_size = msg.max_packet_size.unwrap_or(DEFAULT_MAX_PACKET_SIZE);
+    validate_limit("max_packet_size", max_packet_size)?;
+    if let Some(tip) = &msg.keeper_tip {
+        validate_fee(tip)?;
+    }
+    let ibc_transfer_timeout = msg.ibc_transfer_timeout.unwrap_or(PACKET_LIFETIME);
+    validate_timeout("ibc_transfer_timeout", ibc_transfer_timeout)?;
+    let ibc_hop_timeout = msg.ibc_hop_timeout.unwrap_or(HOP_PACKET_LIFETIME);
+    validate_timeout("ibc_hop_timeout", ibc_hop_timeout)?;
+    let max_depth = msg.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+    validate_max_depth(max_depth)?;
+    let unbonding_period = msg.unbonding_period.unwrap_or(DEFAULT_UNBONDING_PERIOD);
+    validate_limit("unbonding_period", unbonding_period)?;
+    let config = Config {
+        admin: match msg.admin {
+            Some(admin) => deps.api.addr_validate(&admin)?,
+            None => info.sender,
+        },
+        ibc_transfer_timeout,
+        ibc_hop_timeout,
+        fee: msg.fee,
+        command_fees: msg.command_fees,
+        allowed_targets: validate_addresses(deps.api, &msg.allowed_targets)?,
+        allowed_channels: msg.allowed_channels,
+        allowed_pools: msg.allowed_pools,
+        allowed_contracts: validate_addresses(deps.api, &msg.allowed_contracts)?,
+        allowed_type_urls: msg.allowed_type_urls,
+        cw20_ics20_contract: msg
+            .cw20_ics20_contract
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        max_referral_bps: msg.max_referral_bps,
+        referral_mode: msg.referral_mode,
+        max_depth,
+        max_commands,
+        max_packet_size,
+        keeper_tip: msg.keeper_tip,
+        unbonding_period,
+    };
+    CONFIG.save(deps.storage, &config)?;
+
     Ok(Response::new()
-        .add_attribute("method", "instantiate"))
+        .add_attribute("method", "instantiate")
+        .add_attribute("admin", config.admin))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
-    deps: DepsMut,
-    _env: Env,
+    mut deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    // coins that came back since the last call are reserved before anyone can claim them
+    settle_incoming(deps.branch(), &env)?;
     match msg {
-        ExecuteMsg::Transfer { address } => execute_transfer(deps, info, address),
-        ExecuteMsg::IbcTransfer { channel_id, address } => execute_ibc_transfer(deps, _env, info, channel_id, address),
-        ExecuteMsg::Swap { pool_id, token_out_denom, token_out_min_amount } => execute_swap(_env.contract.address.into(), info, pool_id, token_out_denom, token_out_min_amount),
-        ExecuteMsg::PurchaseNFT { owner, contract_addr, token_id, token_uri } => purchase_nft(deps, _env, info, contract_addr, token_id, token_uri, owner),
-        ExecuteMsg::ContractHop { contract_addr, commands } => contract_hop(deps, info, contract_addr, commands),
-        ExecuteMsg::IbcContractHop { channel, commands } => execute_ibc_contract_hop(_env, channel, commands),
+        ExecuteMsg::Receive(wrapper) => {
+            let received = vec![Asset::cw20(info.sender, wrapper.amount)];
+            let sender = deps.api.addr_validate(&wrapper.sender)?;
+            let caller = resolve_cw20_caller(deps.storage, &env, &received, sender)?;
+            match from_binary(&wrapper.msg)? {
+                ExecuteMsg::Receive(_) => Err(ContractError::UnsupportedCw20Command {}),
+                ExecuteMsg::If { .. } => Err(ContractError::PipelineOnly { command: "If".to_string() }),
+                ExecuteMsg::SchedulePipeline { commands, trigger } => schedule_pipeline(deps, env, caller, received, commands, trigger),
+                msg => {
+                    validate_command(deps.as_ref(), &env, &msg)?;
+                    execute_command(deps, env, caller, received, msg)
+                }
+            }
+        }
+        ExecuteMsg::UpdateConfig {
+            ibc_transfer_timeout,
+            ibc_hop_timeout,
+            fee,
+            command_fees,
+            allowed_targets,
+            cw20_ics20_contract,
+            max_referral_bps,
+            referral_mode,
+            max_depth,
+            max_commands,
+            max_packet_size,
+            keeper_tip,
+            unbonding_period,
+            clear,
+        } => update_config(
+            deps,
+            info,
+            ConfigUpdate {
+                ibc_transfer_timeout,
+                ibc_hop_timeout,
+                fee,
+                command_fees,
+                allowed_targets,
+                cw20_ics20_contract,
+                max_referral_bps,
+                referral_mode,
+                max_depth,
+                max_commands,
+                max_packet_size,
+                keeper_tip,
+                unbonding_period,
+                clear,
+            },
+        ),
+        ExecuteMsg::UpdateAllowlist {
+            add_targets,
+            remove_targets,
+            add_channels,
+            remove_channels,
+            add_pools,
+            remove_pools,
+            add_contracts,
+            remove_contracts,
+            add_type_urls,
+            remove_type_urls,
+        } => update_allowlist(
+            deps,
+            info,
+            AllowlistUpdate {
+                add_targets,
+                remove_targets,
+                add_channels,
+                remove_channels,
+                add_pools,
+                remove_pools,
+                add_contracts,
+                remove_contracts,
+                add_type_urls,
+                remove_type_urls,
+            },
+        ),
+        ExecuteMsg::ProposeNewOwner { owner } => propose_new_owner(deps, info, owner),
+        ExecuteMsg::AcceptOwnership {} => accept_ownership(deps, info),
+        ExecuteMsg::SetPause {
+            transfers,
+            swaps,
+            nft,
+            hops,
+            ibc_receive,
+            staking,
+            governance,
+        } => set_pause(
+            deps,
+            info,
+            PauseUpdate {
+                transfers,
+                swaps,
+                nft,
+                hops,
+                ibc_receive,
+                staking,
+                governance,
+            },
+        ),
+        ExecuteMsg::WithdrawFees { asset_info, recipient } => withdraw_fees(deps, info, asset_info, recipient),
+        ExecuteMsg::ClaimReferralFees {} => claim_referral_fees(deps, info),
+        ExecuteMsg::SchedulePipeline { commands, trigger } => {
+            let caller = resolve_caller(deps.storage, &env, info.sender)?;
+            let funds = info.funds.into_iter().map(Asset::from).collect();
+            schedule_pipeline(deps, env, caller, funds, commands, trigger)
+        }
+        ExecuteMsg::If { .. } => Err(ContractError::PipelineOnly { command: "If".to_string() }),
+        ExecuteMsg::ExecuteScheduled { id } => execute_scheduled(deps, env, info, id),
+        ExecuteMsg::CancelScheduled { id } => cancel_scheduled(deps, info, id),
+        ExecuteMsg::PlaceLimitOrder { pool_id, token_out_denom, min_price, expiry } => {
+            let caller = resolve_caller(deps.storage, &env, info.sender)?;
+            let funds = info.funds.into_iter().map(Asset::from).collect();
+            place_limit_order(deps, env, caller, funds, OrderTerms { pool_id, token_out_denom, min_price, expiry })
+        }
+        ExecuteMsg::FillLimitOrder { id } => fill_limit_order(deps, env, info, id),
+        ExecuteMsg::CancelLimitOrder { id } => cancel_limit_order(deps, env, info, id),
+        ExecuteMsg::CreateDca { pool_routes, amount_per_interval, interval_seconds, occurrences, destination, min_price } => {
+            let caller = resolve_caller(deps.storage, &env, info.sender)?;
+            let funds = info.funds.into_iter().map(Asset::from).collect();
+            let terms = DcaTerms { pool_routes, amount_per_interval, interval_seconds, occurrences, destination, min_price };
+            create_dca(deps, env, caller, funds, terms)
+        }
+        ExecuteMsg::ExecuteDca { id } => execute_dca(deps, env, info, id),
+        ExecuteMsg::CancelDca { id } => cancel_dca(deps, info, id),
+        ExecuteMsg::ClaimUnlocked { lock_id } => claim_unlocked(deps, env, info, lock_id),
+        ExecuteMsg::ClaimUnbonded { id } => claim_unbonded(deps, env, info, id),
+        ExecuteMsg::MintReceipt { position } => mint_receipt(deps, env, info, position),
+        ExecuteMsg::RedeemReceipt {} => redeem_receipt(deps, env, info),
+        ExecuteMsg::DepositRemote { channel, sender } => deposit_remote(deps, info, channel, sender),
+        ExecuteMsg::WithdrawRemote { channel, funds, recipient } => withdraw_remote(deps, env, info, channel, funds, recipient),
+        msg => {
+            let caller = resolve_caller(deps.storage, &env, info.sender)?;
+            validate_command(deps.as_ref(), &env, &msg)?;
+            let received = info.funds.into_iter().map(Asset::from).collect();
+            execute_command(deps, env, caller, received, msg)
+        }
     }
 }
 
-fn execute_ibc_contract_hop(env: Env, channel: String, commands: Vec<ExecuteMsg>) -> Result<Response, ContractError> {
+/// Runs a command with the assets received either as native funds or through CW20 `Send`
+fn execute_command(
+    deps: DepsMut,
+    env: Env,
+    caller: Caller,
+    received: Vec<Asset>,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &msg)?;
+    // admin messages are not pipeline commands
+    let kind = msg.kind().ok_or(ContractError::Unauthorized {})?;
+    let assets = select_assets(&msg, &caller, received)?;
+    let referrer = msg
+        .referrer()
+        .map(|referrer| -> StdResult<_> { Ok((deps.api.addr_validate(&referrer.address)?, referrer.bps)) })
+        .transpose()?;
+    let (assets, charges) = charge_fee(
+        deps.storage,
+        kind,
+        referrer.as_ref().map(|(address, bps)| (address, *bps)),
+        assets,
+    )?;
+    let mut attributes = execution_attributes(deps.storage, &caller)?;
+
+    let response = match msg {
+        ExecuteMsg::Transfer { address, .. } => execute_transfer(deps, assets, address),
+        ExecuteMsg::IbcTransfer { channel_id, address, .. } => execute_ibc_transfer(deps, env, assets, channel_id, address),
+        ExecuteMsg::Swap { pool_id, token_out_denom, token_out_min_amount, .. } => execute_swap(env.contract.address.into(), assets, pool_id, token_out_denom, token_out_min_amount),
+        ExecuteMsg::JoinPool { pool_id, share_out_amount, .. } => execute_join_pool(env.contract.address.into(), assets, pool_id, share_out_amount),
+        ExecuteMsg::ExitPool { pool_id, token_out_mins, .. } => execute_exit_pool(env.contract.address.into(), assets, pool_id, token_out_mins),
+        ExecuteMsg::LockTokens { duration, .. } => execute_lock_tokens(deps, env, caller, assets, duration),
+        ExecuteMsg::SuperfluidDelegate { validator, .. } => execute_superfluid_delegate(deps, env, caller, assets, validator),
+        ExecuteMsg::Unlock { lock_id } => execute_unlock(deps, env, caller, assets, lock_id),
+        ExecuteMsg::Delegate { validator, .. } => execute_delegate(deps, env, caller, assets, validator),
+        ExecuteMsg::Undelegate { validator, amount } => execute_undelegate(deps, env, caller, assets, validator, amount),
+        ExecuteMsg::Redelegate { src_validator, dst_validator, amount } => execute_redelegate(deps, env, caller, assets, src_validator, dst_validator, amount),
+        ExecuteMsg::ClaimRewards { validator } => execute_claim_rewards(deps, env, caller, assets, validator),
+        ExecuteMsg::Vote { proposal_id, option } => {
+            let options = vec![WeightedVoteOption { option, weight: Decimal::one() }];
+            execute_vote(deps, env, caller, assets, kind, proposal_id, options)
+        }
+        ExecuteMsg::VoteWeighted { proposal_id, options } => execute_vote(deps, env, caller, assets, kind, proposal_id, options),
+        ExecuteMsg::WasmExecute { contract_addr, msg, .. } => execute_wasm(assets, contract_addr, msg),
+        ExecuteMsg::StargateMsg { type_url, value } => execute_stargate(caller, assets, type_url, value),
+        ExecuteMsg::PurchaseNFT { owner, contract_addr, token_id, token_uri, .. } => purchase_nft(deps, env, assets, contract_addr, token_id, token_uri, owner),
+        ExecuteMsg::ContractHop { contract_addr, commands, .. } => contract_hop(deps, env, caller, assets, contract_addr, commands),
+        ExecuteMsg::IbcContractHop { channel, commands, remote_funds, .. } => execute_ibc_contract_hop(deps, env, caller, assets, channel, commands, remote_funds),
+        _ => Err(ContractError::Unauthorized {}),
+    }?;
+
+    let mut response = response;
+    if !charges.protocol.is_empty() {
+        response = response.add_attribute("fee", join_assets(&charges.protocol));
+        attributes.extend(asset_attributes("fee_", &charges.protocol));
+    }
+    if let Some((referrer, _)) = referrer.filter(|_| !charges.referral.is_empty()) {
+        attributes.push(Attribute::new("referrer", &referrer));
+        attributes.extend(asset_attributes("referral_fee_", &charges.referral));
+        response = response
+            .add_attribute("referrer", referrer)
+            .add_attribute("referral_fee", join_assets(&charges.referral));
+    }
+    // the typed command event says who ran it and where in a pipeline
+    for event in response.events.iter_mut() {
+        event.attributes.extend(attributes.clone());
+    }
+    Ok(response)
+}
+
+/// Assets a command spends: the requested one, checked against what was received, or everything
+/// received. What a `WasmExecute` sent by an account selects has to be all it received, a
+/// pipeline step was only handed its selection.
+fn select_assets(msg: &ExecuteMsg, caller: &Caller, received: Vec<Asset>) -> Result<Vec<Asset>, ContractError> {
+    if let ExecuteMsg::WasmExecute { funds: spec, .. } = msg {
+        if *caller != Caller::Pipeline {
+            let selected = spec.select(&received);
+            let unused = deduct(&received, &selected)?;
+            if !unused.is_empty() {
+                return Err(ContractError::FundsMismatch {
+                    expected: if selected.is_empty() { "no funds".to_string() } else { join_assets(&selected) },
+                    received: join_assets(&received),
+                });
+            }
+            return Ok(selected);
+        }
+    }
+    match msg.asset() {
+        Some(asset) => {
+            asset.assert_received(&received)?;
+            Ok(vec![asset.clone()])
+        }
+        None => Ok(received),
+    }
+}
+
+/// Single asset a command spends
+fn single_asset(mut assets: Vec<Asset>) -> Result<Asset, ContractError> {
+    match (assets.pop(), assets.is_empty()) {
+        (Some(asset), true) => Ok(asset),
+        (None, _) => Err(ContractError::Std(StdError::generic_err(
+            "you must send the coins you wish to use",
+        ))),
+        _ => Err(ContractError::SingleAssetRequired {}),
+    }
+}
+
+fn execute_ibc_contract_hop(
+    deps: DepsMut,
+    env: Env,
+    caller: Caller,
+    assets: Vec<Asset>,
+    channel: String,
+    commands: Vec<ExecuteMsg>,
+    remote_funds: Vec<Coin>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    // the packet carries no funds, so nothing is charged here either: the remote funds pay
+    // the fees of the commands spending them on the other end
+    assert_no_funds(&assets)?;
+    let origin = match caller {
+        Caller::Pipeline => running_step(deps.storage)?,
+        _ => None,
+    };
+    // the counterparty spends the remote funds out of the account of whoever this runs for
+    let sender = packet_sender(deps.storage, &caller)?.ok_or(ContractError::UnknownRemoteSender {})?;
+
+    let event = command_event(CommandKind::IbcContractHop)
+        .add_attribute("channel", &channel)
+        .add_attribute("commands", commands.len().to_string())
+        .add_attributes(asset_attributes("remote_", &remote_funds.iter().cloned().map(Asset::from).collect::<Vec<_>>()));
+
+    // the sender and origin only known now may push the packet over the limit
+    let data = to_binary(&IbcExecuteMsg::IbcContractHop {
+        commands,
+        funds: remote_funds,
+        sender: Some(sender),
+        origin,
+    })?;
+    validate_packet_size(&config, &data)?;
+
     Ok(Response::new()
         .add_attribute("method", "execute_ibc_contract_hop")
         .add_attribute("channel", channel.clone())
+        .add_event(event)
         .add_message(IbcMsg::SendPacket {
             channel_id: channel,
-            data: to_binary(&IbcExecuteMsg::IbcContractHop { commands })?,
-            timeout: IbcTimeout::with_timestamp(env.block.time.plus_seconds(300)),
+            data,
+            timeout: IbcTimeout::with_timestamp(env.block.time.plus_seconds(config.ibc_hop_timeout)),
         }))
 }
 
-pub fn execute_transfer(deps: DepsMut, info: MessageInfo, addr: String) -> Result<Response, ContractError> {
+pub fn execute_transfer(deps: DepsMut, assets: Vec<Asset>, addr: String) -> Result<Response, ContractError> {
     let to_addr = match deps.api.addr_validate(addr.clone().as_str()).ok() {
         Some(x) => x,
         None => return Err(ContractError::Unauthorized {}),
     };
 
-    let sent_funds = info.funds.clone();
-    let msg = BankMsg::Send {
-        to_address: to_addr.into(),
-        amount: sent_funds,
-    };
+    let msgs = assets
+        .iter()
+        .map(|asset| asset.transfer_msg(to_addr.as_str()))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let event = command_event(CommandKind::Transfer)
+        .add_attribute("recipient", &to_addr)
+        .add_attributes(asset_attributes("", &assets));
 
     Ok(Response::new()
         .add_attribute("method", "execute_transfer")
-        .add_message(msg)
+        .add_event(event)
+        .add_messages(msgs)
     )
 }
 
-pub fn execute_ibc_transfer(_deps: DepsMut, env: Env, mut info: MessageInfo, channel_id: String, addr: String) -> Result<Response, ContractError> {
+pub fn execute_ibc_transfer(deps: DepsMut, env: Env, assets: Vec<Asset>, channel_id: String, addr: String) -> Result<Response, ContractError> {
     // require some funds
-    let amount = match info.funds.pop() {
-        Some(coin) => coin,
-        None => {
-            return Err(ContractError::Std(StdError::generic_err(
-                "you must send the coins you wish to ibc transfer",
-            )))
+    let asset = single_asset(assets)?;
+    let event = command_event(CommandKind::IbcTransfer)
+        .add_attribute("channel", &channel_id)
+        .add_attribute("recipient", &addr)
+        .add_attributes(asset_attributes("", std::slice::from_ref(&asset)));
+    let token = match asset.info {
+        AssetInfo::Native(denom) => denom,
+        AssetInfo::Cw20(token) => {
+            let response = execute_cw20_ibc_transfer(deps, env, token, asset.amount, channel_id, addr)?;
+            return Ok(response.add_event(event));
         }
     };
 
     // construct a packet to send
+    let config = CONFIG.load(deps.storage)?;
     let msg = IbcMsg::Transfer {
         channel_id,
         to_address: addr,
-        amount,
-        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+        amount: Coin::new(asset.amount.u128(), token),
+        timeout: env.block.time.plus_seconds(config.ibc_transfer_timeout).into(),
+    };
+
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("method", "execute_ibc_transfer")
+        .add_event(event))
+}
+
+pub fn execute_cw20_ibc_transfer(deps: DepsMut, _env: Env, token: Addr, amount: Uint128, channel_id: String, addr: String) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let ics20_contract = config
+        .cw20_ics20_contract
+        .ok_or(ContractError::Cw20Ics20NotConfigured {})?;
+
+    // cw20-ics20 takes the tokens through `Send` and reads the destination from the payload
+    let transfer = TransferMsg {
+        channel: channel_id,
+        remote_address: addr,
+        timeout: Some(config.ibc_transfer_timeout),
     };
+    let msg = Asset::cw20(token, amount).execute_msg(ics20_contract.as_str(), to_binary(&transfer)?)?;
 
     Ok(Response::new()
         .add_message(msg)
-        .add_attribute("action", "execute_ibc_transfer"))
+        .add_attribute("method", "execute_ibc_transfer"))
 }
 
-pub fn execute_swap(self_address: String, info: MessageInfo, pool_id: u64, token_out_denom: String, token_out_min_amount: String) -> Result<Response, ContractError> {
-    let funds = info.funds.clone().pop().unwrap();
+pub fn execute_swap(self_address: String, assets: Vec<Asset>, pool_id: u64, token_out_denom: String, token_out_min_amount: Uint128) -> Result<Response, ContractError> {
+    let asset = single_asset(assets)?;
+    let event = command_event(CommandKind::Swap)
+        .add_attribute("pool_id", pool_id.to_string())
+        .add_attribute("token_out_denom", &token_out_denom)
+        .add_attribute("token_out_min_amount", token_out_min_amount)
+        .add_attributes(asset_attributes("", std::slice::from_ref(&asset)));
+    let funds = asset.to_coin()?;
     let coin = Osmo_Coin {
         denom: funds.denom,
         amount: funds.amount.to_string()
     };
 
-    let mut osmo_routes: Vec<Osmo_SwapAmountInRoute> = Vec::new();
-    osmo_routes.push(Osmo_SwapAmountInRoute {
+    let osmo_routes: Vec<Osmo_SwapAmountInRoute> = vec![Osmo_SwapAmountInRoute {
         pool_id,
         token_out_denom
-    });
+    }];
 
     let msg = MsgSwapExactAmountIn {
         sender: self_address,
         routes: osmo_routes,
         token_in: Option::from(coin),
-        token_out_min_amount,
+        token_out_min_amount: token_out_min_amount.to_string(),
     };
 
     let msg = msg.to_msg()?;
     Ok(Response::new()
         .add_attribute("method", "execute_swap")
+        .add_event(event)
         .add_message(msg))
 }
 
+pub fn execute_join_pool(self_address: String, assets: Vec<Asset>, pool_id: u64, share_out_amount: Uint128) -> Result<Response, ContractError> {
+    let coins = assets
+        .iter()
+        .map(Asset::to_coin)
+        .collect::<Result<Vec<_>, _>>()?;
+    let event = command_event(CommandKind::JoinPool)
+        .add_attribute("pool_id", pool_id.to_string())
+        .add_attribute("share_out_amount", share_out_amount)
+        .add_attributes(asset_attributes("", &assets));
+
+    let msg = match coins.as_slice() {
+        [] => return Err(ContractError::Std(StdError::generic_err(
+            "you must send the coins you wish to use",
+        ))),
+        [coin] => MsgJoinSwapExternAmountIn {
+            sender: self_address,
+            pool_id,
+            token_in: Some(proto_coin(coin)),
+            share_out_min_amount: share_out_amount.to_string(),
+        }
+        .to_msg()?,
+        // the pool takes the coins in its own ratio, the rest stays with the pipeline
+        _ => stargate_msg(
+            JOIN_POOL_TYPE_URL,
+            &MsgJoinPool {
+                sender: self_address,
+                pool_id,
+                share_out_amount: share_out_amount.to_string(),
+                token_in_maxs: coins.iter().map(proto_coin).collect(),
+            },
+        ),
+    };
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_join_pool")
+        .add_event(event)
+        .add_message(msg))
+}
+
+pub fn execute_exit_pool(self_address: String, assets: Vec<Asset>, pool_id: u64, token_out_mins: Vec<Coin>) -> Result<Response, ContractError> {
+    let shares = single_asset(assets)?;
+    let expected = share_denom(pool_id);
+    if shares.info != AssetInfo::Native(expected.clone()) {
+        return Err(ContractError::FundsMismatch {
+            expected,
+            received: shares.to_string(),
+        });
+    }
+    let event = command_event(CommandKind::ExitPool)
+        .add_attribute("pool_id", pool_id.to_string())
+        .add_attributes(asset_attributes("", std::slice::from_ref(&shares)));
+
+    let msg = stargate_msg(
+        EXIT_POOL_TYPE_URL,
+        &MsgExitPool {
+            sender: self_address,
+            pool_id,
+            share_in_amount: shares.amount.to_string(),
+            token_out_mins: token_out_mins.iter().map(proto_coin).collect(),
+        },
+    );
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_exit_pool")
+        .add_event(event)
+        .add_message(msg))
+}
+
+/// Calls the contract with all the assets the step was handed, the pipeline picked them
+/// through the `AmountSpec`
+pub fn execute_wasm(assets: Vec<Asset>, contract_addr: String, msg: Binary) -> Result<Response, ContractError> {
+    let event = command_event(CommandKind::WasmExecute)
+        .add_attribute("contract", &contract_addr)
+        .add_attributes(asset_attributes("", &assets));
+    let msg = execute_msg_with_assets(&contract_addr, msg, &assets)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_wasm")
+        .add_event(event)
+        .add_message(msg))
+}
+
+/// Only pipeline steps, whose balance change is checked against the pipeline funds, may
+/// send a message that spends from the contract balance
+pub fn execute_stargate(caller: Caller, assets: Vec<Asset>, type_url: String, value: Binary) -> Result<Response, ContractError> {
+    if caller != Caller::Pipeline {
+        return Err(ContractError::PipelineOnly { command: "StargateMsg".to_string() });
+    }
+    assert_no_funds(&assets)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_stargate")
+        .add_event(command_event(CommandKind::StargateMsg).add_attribute("type_url", &type_url))
+        .add_message(CosmosMsg::Stargate { type_url, value }))
+}
+
 // todo: Purchase logic implemented via nft mint just for HackAtom explanation,
 //  need to change it to the real NFT purchase on market
-pub fn purchase_nft(_deps: DepsMut, _env: Env, info: MessageInfo, contract_addr: String, token_id: String, token_uri: String, owner: String) -> Result<Response, ContractError> {
+pub fn purchase_nft(_deps: DepsMut, _env: Env, assets: Vec<Asset>, contract_addr: String, token_id: String, token_uri: String, owner: String) -> Result<Response, ContractError> {
     let mint_msg = Cw721ExecuteMsg::Mint(MintMsg::<Extension> {
         token_id: token_id.to_string(),
         owner: owner.clone(),
@@ -139,21 +628,31 @@ pub fn purchase_nft(_deps: DepsMut, _env: Env, info: MessageInfo, contract_addr:
         extension: Option::None
     });
 
+    let event = command_event(CommandKind::PurchaseNft)
+        .add_attribute("nft_contract", &contract_addr)
+        .add_attribute("token_id", &token_id)
+        .add_attribute("owner", &owner)
+        .add_attributes(asset_attributes("", &assets));
+    let funds = assets
+        .iter()
+        .map(Asset::to_coin)
+        .collect::<Result<Vec<_>, _>>()?;
     let msg = WasmMsg::Execute {
         contract_addr: contract_addr.clone(),
         msg: to_binary(&mint_msg)?,
-        funds: info.funds.clone(),
+        funds,
     };
 
     Ok(Response::new()
         .add_message(msg)
-        .add_attribute("action", "purchaseNft"))
+        .add_attribute("method", "purchase_nft")
+        .add_event(event))
 }
 
-pub fn contract_hop(deps: DepsMut, info: MessageInfo, contract_addr: String, mut commands: Vec<ExecuteMsg>) -> Result<Response, ContractError> {
-    match deps.api.addr_validate(contract_addr.as_str()).ok() {
+pub fn contract_hop(deps: DepsMut, env: Env, caller: Caller, received: Vec<Asset>, contract_addr: String, commands: Vec<ExecuteMsg>) -> Result<Response, ContractError> {
+    let contract_addr = match deps.api.addr_validate(contract_addr.as_str()).ok() {
         None => return Err(ContractError::Unauthorized {}),
-        Some(addr) => CONTRACT_ADDRESS.save(deps.storage, &addr)?,
+        Some(addr) => addr,
     };
 
     // todo: need to fix it:
@@ -171,161 +670,136 @@ pub fn contract_hop(deps: DepsMut, info: MessageInfo, contract_addr: String, mut
     //     }
     // }).collect();
 
-    // let funds: Vec<_> = info.funds;
-    // let mut funds = info.funds;
-
-
-    let msgs = if let Some(command) = commands.pop() {
-        let msg = match command {
-            ExecuteMsg::Transfer { address } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::Transfer { address }).unwrap(),
-                    funds: info.funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::IbcTransfer { channel_id, address } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::IbcTransfer { channel_id, address }).unwrap(),
-                    funds: info.funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::Swap { pool_id, token_out_denom, token_out_min_amount } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::Swap {
-                        pool_id,
-                        token_out_denom,
-                        token_out_min_amount
-                    }).unwrap(),
-                    funds: info.funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::PurchaseNFT { owner, contract_addr, token_id, token_uri } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::PurchaseNFT {
-                        owner,
-                        contract_addr,
-                        token_id,
-                        token_uri
-                    }).unwrap(),
-                    funds: info.funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::ContractHop { contract_addr, commands } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::ContractHop { contract_addr, commands }).unwrap(),
-                    funds: info.funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::IbcContractHop { channel, commands } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::IbcContractHop { channel, commands }).unwrap(),
-                    funds: info.funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-        };
-        vec![msg]
-    } else { vec![] };
-
-    COMMANDS_STACK.save(deps.storage, &commands)?;
+    // a hop nested in a pipeline leaves its unspent funds to the outer pipeline
+    let mut event = command_event(CommandKind::ContractHop)
+        .add_attribute("target", &contract_addr)
+        .add_attribute("commands", commands.len().to_string())
+        .add_attributes(asset_attributes("", &received));
+    let (id, msgs) = start_pipeline(deps, &env, contract_addr, commands, received, &caller, caller.beneficiary())?;
+    if let Some(id) = id {
+        event = event.add_attribute("pipeline_id", id.to_string());
+    }
 
     Ok(Response::new()
         .add_attribute("method", "contract_hop")
+        .add_event(event)
         .add_submessages(msgs))
 }
 
-// I don't know, can I delete this reply or not that's wy allow dead_code
-#[allow(dead_code)]
-#[entry_point]
-fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
-    match msg.id { 1 => {
-        hop_reply(deps, env, msg.result)
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => to_binary(&CONFIG.load(deps.storage)?),
+        QueryMsg::PendingOwner {} => to_binary(&PENDING_OWNER.may_load(deps.storage)?),
+        QueryMsg::PauseFlags {} => to_binary(&pause_flags(deps.storage)?),
+        QueryMsg::AccruedFees {} => to_binary(&accrued_fees(deps.storage)?),
+        QueryMsg::ReferralBalance { address } => to_binary(&referral_balance(deps.storage, &deps.api.addr_validate(&address)?)?),
+        QueryMsg::RemoteBalance { channel, sender } => to_binary(&remote_balance(deps.storage, &channel, &sender)?),
+        QueryMsg::SimulatePipeline { commands, funds } => to_binary(&simulate_pipeline(deps, &env, commands, funds)?),
+        QueryMsg::Pipeline { id } => to_binary(&PIPELINE_HISTORY.load(deps.storage, id)?),
+        QueryMsg::Scheduled { id } => to_binary(&SCHEDULED.load(deps.storage, id)?),
+        QueryMsg::ScheduledPipelines { start_after, limit } => to_binary(&ScheduledPipelinesResponse {
+            pipelines: scheduled_pipelines(deps, start_after, limit)?,
+        }),
+        QueryMsg::Dca { id } => to_binary(&DCAS.load(deps.storage, id)?),
+        QueryMsg::Dcas { start_after, limit } => to_binary(&DcasResponse {
+            dcas: dcas(deps, start_after, limit)?,
+        }),
+        QueryMsg::LimitOrder { id } => to_binary(&LIMIT_ORDERS.load(deps.storage, id)?),
+        QueryMsg::Lock { id } => to_binary(&LOCKS.load(deps.storage, id)?),
+        QueryMsg::Delegations { owner, start_after, limit } => {
+            let owner = deps.api.addr_validate(&owner)?;
+            to_binary(&DelegationsResponse {
+                delegations: delegations(deps, &owner, start_after, limit)?,
+            })
+        }
+        QueryMsg::Unbonding { id } => to_binary(&UNBONDINGS.load(deps.storage, id)?),
+        QueryMsg::Receipt { position } => to_binary(&RECEIPTS.load(deps.storage, &position.subdenom())?),
+        QueryMsg::LimitOrders { start_after, limit } => to_binary(&LimitOrdersResponse {
+            orders: limit_orders(deps, start_after, limit)?,
+        }),
+        QueryMsg::PipelinesBySender { sender, start_after, limit } => {
+            let sender = deps.api.addr_validate(&sender)?;
+            let pipelines = pipelines_by_sender(deps.storage, &sender, start_after, limit)?;
+            to_binary(&PipelinesResponse { pipelines })
+        }
     }
-        _ => return Err(ContractError::Unauthorized {})
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        HOP_REPLY_ID => hop_reply(deps, env, msg.result),
+        LOCK_REPLY_ID => lock_reply(deps, msg.result),
+        _ => Err(ContractError::Unauthorized {}),
     }
 }
 
-pub fn hop_reply(deps: DepsMut, env: Env, msg: SubMsgResult) -> Result<Response, ContractError> {
-    msg.into_result().map_err(|err| StdError::generic_err(err))?;
-    let mut commands = COMMANDS_STACK.load(deps.storage)?;
-    let contract_addr = CONTRACT_ADDRESS.load(deps.storage)?.into_string();
-
-    let funds = deps.querier.query_all_balances(env.contract.address)?;
-    let msgs = if let Some(command) = commands.pop() {
-        let msg = match command {
-            ExecuteMsg::Transfer { address } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::Transfer { address }).unwrap(),
-                    funds: funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::IbcTransfer { channel_id, address } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::IbcTransfer { channel_id, address }).unwrap(),
-                    funds: funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::Swap { pool_id, token_out_denom, token_out_min_amount } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::Swap {
-                        pool_id,
-                        token_out_denom,
-                        token_out_min_amount
-                    }).unwrap(),
-                    funds: funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::PurchaseNFT { owner, contract_addr, token_id, token_uri } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::PurchaseNFT {
-                        owner,
-                        contract_addr,
-                        token_id,
-                        token_uri
-                    }).unwrap(),
-                    funds: funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::ContractHop { contract_addr, commands } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::ContractHop { contract_addr, commands }).unwrap(),
-                    funds: funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
-            ExecuteMsg::IbcContractHop { channel, commands } => {
-                let msg = WasmMsg::Execute {
-                    contract_addr: contract_addr.clone(),
-                    msg: to_binary(&ExecuteMsg::IbcContractHop { channel, commands }).unwrap(),
-                    funds: funds.clone(),
-                };
-                SubMsg::reply_on_success(msg, 1)
-            }
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::WrongContract {
+            expected: CONTRACT_NAME.to_string(),
+            actual: stored.contract,
+        });
+    }
+    let stored_version: Version = stored.version.parse()?;
+    let new_version: Version = CONTRACT_VERSION.parse()?;
+    if stored_version > new_version {
+        return Err(ContractError::CannotDowngrade {
+            stored: stored.version,
+            new: CONTRACT_VERSION.to_string(),
+        });
+    }
+
+    // 0.1.0 was instantiated without any configuration
+    if CONFIG.may_load(deps.storage)?.is_none() {
+        let admin = msg.admin.ok_or(ContractError::MissingAdmin {})?;
+        let config = Config {
+            admin: deps.api.addr_validate(&admin)?,
+            ibc_transfer_timeout: PACKET_LIFETIME,
+            ibc_hop_timeout: HOP_PACKET_LIFETIME,
+            fee: None,
+            command_fees: vec![],
+            allowed_targets: vec![],
+            allowed_channels: vec![],
+            allowed_pools: vec![],
+            allowed_contracts: vec![],
+            allowed_type_urls: vec![],
+            cw20_ics20_contract: None,
+            max_referral_bps: 0,
+            referral_mode: ReferralMode::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_commands: DEFAULT_MAX_COMMANDS,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            keeper_tip: None,
+            unbonding_period: DEFAULT_UNBONDING_PERIOD,
         };
-        vec![msg]
-    } else { vec![] };
+        CONFIG.save(deps.storage, &config)?;
+    }
+    // pipelines only run within a transaction, whatever is left cannot be resumed
+    let dropped = drop_stale_pipelines(deps.storage)?;
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    COMMANDS_STACK.save(deps.storage, &commands)?;
     Ok(Response::new()
-        .add_attribute("method", "hop_reply").add_submessages(msgs))
+        .add_attribute("method", "migrate")
+        .add_attribute("from_version", stored_version.to_string())
+        .add_attribute("to_version", CONTRACT_VERSION)
+        .add_attribute("dropped_commands", dropped.to_string()))
+}
+
+/// Drops a pipeline left in the 0.1.0 layout, or by an earlier migration. It cannot go on:
+/// nothing recorded who it ran for or which of the contract funds were its own. Returns how
+/// many commands were dropped.
+fn drop_stale_pipelines(storage: &mut dyn Storage) -> StdResult<usize> {
+    let mut dropped = COMMANDS_STACK.may_load(storage)?.unwrap_or_default().len();
+    for pipeline in PIPELINES.may_load(storage)?.unwrap_or_default() {
+        dropped += pipeline.commands.len();
+    }
+    COMMANDS_STACK.remove(storage);
+    CONTRACT_ADDRESS.remove(storage);
+    PIPELINES.remove(storage);
+    Ok(dropped)
 }
@@ -0,0 +1,229 @@
+use cosmwasm_std::{Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Uint128};
+use cw_storage_plus::Bound;
+
+use crate::admin::assert_not_paused;
+use crate::asset::Asset;
+use crate::events::{asset_attributes, event};
+use crate::fees::{assert_covers_keeper_tip, release, reserve, split_keeper_tip};
+use crate::msg::ExecuteMsg;
+use crate::pipeline::{start_pipeline, Caller};
+use crate::receipt::{assert_no_receipt, position_beneficiary};
+use crate::state::{Dca, DcaDestination, Position, SwapRoute, CONFIG, DCAS, DCA_COUNT, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::validation::validate_commands;
+use crate::ContractError;
+
+/// Fields of `ExecuteMsg::CreateDca`
+pub struct DcaTerms {
+    pub pool_routes: Vec<SwapRoute>,
+    pub amount_per_interval: Uint128,
+    pub interval_seconds: u64,
+    pub occurrences: u32,
+    pub destination: Option<DcaDestination>,
+    pub min_price: Option<Decimal>,
+}
+
+/// One interval of `dca`: a `Swap` per route, each taking all the pipeline owns, then
+/// the transfer to the destination
+fn interval_commands(dca: &Dca) -> Vec<ExecuteMsg> {
+    let last = dca.pool_routes.len() - 1;
+    let mut commands: Vec<ExecuteMsg> = dca
+        .pool_routes
+        .iter()
+        .enumerate()
+        .map(|(index, route)| ExecuteMsg::Swap {
+            pool_id: route.pool_id,
+            token_out_denom: route.token_out_denom.clone(),
+            // Osmosis wants a positive minimum, intermediate swaps take any output and
+            // the last one bounds the price of the whole route
+            token_out_min_amount: match dca.min_price {
+                Some(min_price) if index == last => dca.amount_per_interval * min_price,
+                _ => Uint128::new(1),
+            },
+            asset: None,
+            referrer: None,
+        })
+        .collect();
+    match &dca.destination {
+        Some(DcaDestination::Address(address)) => commands.push(ExecuteMsg::Transfer {
+            address: address.clone(),
+            asset: None,
+            referrer: None,
+        }),
+        Some(DcaDestination::Ibc { channel_id, address }) => commands.push(ExecuteMsg::IbcTransfer {
+            channel_id: channel_id.clone(),
+            address: address.clone(),
+            asset: None,
+            referrer: None,
+        }),
+        // what the pipeline leaves goes back to the owner
+        None => {}
+    }
+    commands
+}
+
+pub fn create_dca(deps: DepsMut, env: Env, caller: Caller, funds: Vec<Asset>, terms: DcaTerms) -> Result<Response, ContractError> {
+    let DcaTerms {
+        pool_routes,
+        amount_per_interval,
+        interval_seconds,
+        occurrences,
+        destination,
+        min_price,
+    } = terms;
+    let owner = caller.refund_address().ok_or(ContractError::Unauthorized {})?;
+    if pool_routes.is_empty() {
+        return Err(ContractError::ZeroAmount { field: "pool_routes".to_string() });
+    }
+    if amount_per_interval.is_zero() {
+        return Err(ContractError::ZeroAmount { field: "amount_per_interval".to_string() });
+    }
+    if interval_seconds == 0 {
+        return Err(ContractError::ZeroAmount { field: "interval_seconds".to_string() });
+    }
+    if occurrences == 0 {
+        return Err(ContractError::ZeroAmount { field: "occurrences".to_string() });
+    }
+    if min_price == Some(Decimal::zero()) {
+        return Err(ContractError::ZeroAmount { field: "min_price".to_string() });
+    }
+
+    // the whole schedule is paid upfront in one native coin
+    let escrow = match funds.as_slice() {
+        [escrow] => escrow.clone(),
+        _ => return Err(ContractError::SingleAssetRequired {}),
+    };
+    let denom = escrow.to_coin()?.denom;
+    Asset::native(denom, amount_per_interval * Uint128::from(occurrences)).assert_received(&funds)?;
+
+    let id = DCA_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    let dca = Dca {
+        id,
+        owner,
+        pool_routes,
+        amount_per_interval,
+        interval_seconds,
+        remaining_occurrences: occurrences,
+        destination,
+        min_price,
+        escrow,
+        next_run: env.block.time,
+        created_at: env.block.time,
+    };
+    let commands = interval_commands(&dca);
+    for command in &commands {
+        assert_not_paused(deps.storage, command)?;
+    }
+    validate_commands(deps.as_ref(), &env, &commands)?;
+    let interval = Asset {
+        info: dca.escrow.info.clone(),
+        amount: amount_per_interval,
+    };
+    assert_covers_keeper_tip(&CONFIG.load(deps.storage)?, &[interval])?;
+
+    DCA_COUNT.save(deps.storage, &id)?;
+    reserve(deps.storage, &dca.escrow)?;
+    DCAS.save(deps.storage, id, &dca)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "create_dca")
+        .add_attribute("id", id.to_string())
+        .add_event(
+            event("create_dca")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", &dca.owner)
+                .add_attribute("occurrences", occurrences.to_string())
+                .add_attribute("interval_seconds", interval_seconds.to_string())
+                .add_attributes(asset_attributes("", std::slice::from_ref(&dca.escrow))),
+        ))
+}
+
+pub fn execute_dca(deps: DepsMut, env: Env, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let mut dca = DCAS.load(deps.storage, id)?;
+    if env.block.time < dca.next_run {
+        return Err(ContractError::DcaNotDue { id });
+    }
+    // allowlists and limits may have changed since the DCA was created
+    let commands = interval_commands(&dca);
+    validate_commands(deps.as_ref(), &env, &commands)?;
+
+    // the last interval takes whatever is left
+    let amount = if dca.remaining_occurrences == 1 {
+        dca.escrow.amount
+    } else {
+        dca.amount_per_interval.min(dca.escrow.amount)
+    };
+    let input = Asset {
+        info: dca.escrow.info.clone(),
+        amount,
+    };
+    release(deps.storage, &input)?;
+    dca.escrow.amount -= amount;
+    dca.remaining_occurrences -= 1;
+    // a late run pushes the next one back, keepers cannot catch up with several swaps at once
+    dca.next_run = env.block.time.plus_seconds(dca.interval_seconds);
+    if dca.remaining_occurrences == 0 {
+        DCAS.remove(deps.storage, id);
+    } else {
+        DCAS.save(deps.storage, id, &dca)?;
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let (funds, tip) = split_keeper_tip(&config, vec![input])?;
+    let tip_msgs = tip
+        .iter()
+        .map(|asset| asset.transfer_msg(info.sender.as_str()))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let caller = Caller::External(dca.owner.clone());
+    let beneficiary = position_beneficiary(deps.storage, &Position::Dca(id), &dca.owner)?;
+    let (pipeline_id, msgs) = start_pipeline(deps, &env, env.contract.address.clone(), commands, funds, &caller, beneficiary)?;
+    let mut event = event("execute_dca")
+        .add_attribute("id", id.to_string())
+        .add_attribute("owner", &dca.owner)
+        .add_attribute("keeper", &info.sender)
+        .add_attribute("remaining_occurrences", dca.remaining_occurrences.to_string())
+        .add_attributes(asset_attributes("tip_", &tip));
+    if let Some(pipeline_id) = pipeline_id {
+        event = event.add_attribute("pipeline_id", pipeline_id.to_string());
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_dca")
+        .add_attribute("id", id.to_string())
+        .add_event(event)
+        .add_submessages(msgs)
+        .add_messages(tip_msgs))
+}
+
+pub fn cancel_dca(deps: DepsMut, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let dca = DCAS.load(deps.storage, id)?;
+    if dca.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    assert_no_receipt(deps.storage, &Position::Dca(id))?;
+    DCAS.remove(deps.storage, id);
+    release(deps.storage, &dca.escrow)?;
+
+    let mut response = Response::new()
+        .add_attribute("method", "cancel_dca")
+        .add_attribute("id", id.to_string())
+        .add_event(
+            event("cancel_dca")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", &dca.owner)
+                .add_attributes(asset_attributes("", std::slice::from_ref(&dca.escrow))),
+        );
+    if !dca.escrow.amount.is_zero() {
+        response = response.add_message(dca.escrow.transfer_msg(dca.owner.as_str())?);
+    }
+    Ok(response)
+}
+
+pub fn dcas(deps: Deps, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Vec<Dca>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    DCAS.range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, dca)| dca))
+        .collect()
+}
@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Decimal, StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,11 +12,146 @@ pub enum ContractError {
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("only the contract admin can do this")]
+    NotAdmin {},
+
+    #[error("{capability} are paused")]
+    Paused { capability: String },
+
+    #[error("invalid fee: {reason}")]
+    InvalidFee { reason: String },
+
+    #[error("{field} must be between {min} and {max}")]
+    OutOfRange { field: String, min: u64, max: u64 },
+
+    #[error("{field} cannot be set and cleared at once")]
+    SetAndCleared { field: String },
+
+    #[error("{0}")]
+    SemVer(#[from] semver::Error),
+
+    #[error("cannot migrate from contract {actual}, expected {expected}")]
+    WrongContract { expected: String, actual: String },
+
+    #[error("cannot migrate from version {stored} down to {new}")]
+    CannotDowngrade { stored: String, new: String },
+
+    #[error("an admin is required to migrate a contract without configuration")]
+    MissingAdmin {},
+
+    #[error("the command input does not cover the {fee} fee")]
+    InsufficientFee { fee: String },
+
+    #[error("referral share of {bps} bps is above the {max} bps maximum")]
+    ReferralTooHigh { bps: u16, max: u16 },
+
+    #[error("hop target {target} is not allowlisted")]
+    TargetNotAllowed { target: String },
+
+    #[error("IBC channel {channel} is not allowlisted")]
+    ChannelNotAllowed { channel: String },
+
+    #[error("contract {contract} is not allowlisted")]
+    ContractNotAllowed { contract: String },
+
+    #[error("message type {type_url} is not allowlisted")]
+    TypeUrlNotAllowed { type_url: String },
+
+    #[error("pool {pool_id} is not allowlisted")]
+    PoolNotAllowed { pool_id: u64 },
+
+    #[error("pipeline step spent more {asset} than the pipeline owns")]
+    StepOverspent { asset: String },
+
+    #[error("{funds} is more than what is available to spend")]
+    InsufficientFunds { funds: String },
+
+    #[error("nobody to run the IBC contract hop for on the counterparty")]
+    UnknownRemoteSender {},
+
+    #[error("hops are nested deeper than {max} levels")]
+    PipelineTooDeep { max: u32 },
+
+    #[error("pipeline holds {count} commands, at most {max} are allowed")]
+    TooManyCommands { count: usize, max: u32 },
+
+    #[error("swap returns {amount}, less than the minimum {min}")]
+    SwapBelowMinimum { amount: Uint128, min: Uint128 },
+
+    #[error("token {token_id} is already minted")]
+    TokenAlreadyMinted { token_id: String },
+
+    #[error("DCA {id} is not due yet")]
+    DcaNotDue { id: u64 },
+
+    #[error("expiry is already past")]
+    ExpiryPast {},
+
+    #[error("limit order {id} expired")]
+    OrderExpired { id: u64 },
+
+    #[error("limit order {id} asks for {min_price}, the pool price is {price}")]
+    PriceNotReached { id: u64, min_price: Decimal, price: Decimal },
+
+    #[error("{command} can only run as part of a pipeline")]
+    PipelineOnly { command: String },
+
+    #[error("scheduled pipeline {id} cannot run yet")]
+    TriggerNotMet { id: u64 },
+
+    #[error("IBC packet is {size} bytes, at most {max} are allowed")]
+    PacketTooLarge { size: usize, max: u64 },
+
+    #[error("locks and stake need an account to belong to, pipelines started over IBC have none")]
+    NoOriginator {},
+
+    #[error("lock {id} is already unlocking")]
+    LockUnlocking { id: u64 },
+
+    #[error("lock {id} has not unlocked yet")]
+    LockNotUnlocked { id: u64 },
+
+    #[error("{amount} is more than the stake delegated to {validator}")]
+    InsufficientStake { validator: String, amount: Uint128 },
+
+    #[error("unbonding {id} has not completed yet")]
+    UnbondingNotComplete { id: u64 },
+
+    #[error("voting needs stake delegated through the contract")]
+    NoStake {},
+
+    #[error("invalid vote: {reason}")]
+    InvalidVote { reason: String },
+
+    #[error("receipt {denom} is outstanding, redeem it first")]
+    ReceiptOutstanding { denom: String },
+
+    #[error("{position} pays its output to a fixed recipient, a receipt could not claim it")]
+    FixedRecipient { position: String },
+
+    #[error("{denom} is not an outstanding receipt")]
+    UnknownReceipt { denom: String },
+
     #[error("Custom Error val: {val:?}")]
     CustomError { val: String },
     // Add any other custom errors you like here.
     // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
 
+    #[error("cw20-ics20 contract is not configured")]
+    Cw20Ics20NotConfigured {},
+
+    #[error("command is not supported for CW20 tokens")]
+    UnsupportedCw20Command {},
+
+    #[error("funds mismatch: expected {expected}, received {received}")]
+    FundsMismatch { expected: String, received: String },
+
+    #[error("exactly one asset must be sent")]
+    SingleAssetRequired {},
+
+    #[error("{field} must be greater than zero")]
+    ZeroAmount { field: String },
+
     #[error("only unordered channels are supported")]
     OrderedChannel {},
 
@@ -0,0 +1,56 @@
+use cosmwasm_std::{Attribute, Event, StdResult, Storage};
+
+use crate::asset::Asset;
+use crate::history::running_step;
+use crate::msg::CommandKind;
+use crate::pipeline::Caller;
+use crate::state::PIPELINE_HISTORY;
+
+/// Event types are `blazarbit_<name>`
+pub fn event(name: &str) -> Event {
+    Event::new(format!("blazarbit_{}", name))
+}
+
+/// Event emitted by a command handler, e.g. `blazarbit_swap`
+pub fn command_event(kind: CommandKind) -> Event {
+    event(kind.as_str())
+}
+
+/// `<prefix>amount` and `<prefix>denom` attributes listing `assets` in the same order,
+/// none when there are no assets as the chain refuses empty attribute values. The denom
+/// of a CW20 token is its contract address.
+pub fn asset_attributes(prefix: &str, assets: &[Asset]) -> Vec<Attribute> {
+    if assets.is_empty() {
+        return vec![];
+    }
+    let amounts: Vec<String> = assets.iter().map(|asset| asset.amount.to_string()).collect();
+    let denoms: Vec<String> = assets.iter().map(|asset| asset.info.to_string()).collect();
+    vec![
+        Attribute::new(format!("{}amount", prefix), amounts.join(",")),
+        Attribute::new(format!("{}denom", prefix), denoms.join(",")),
+    ]
+}
+
+/// Who a command runs for and, inside a pipeline, which execution and step it is. Pipelines
+/// received over IBC run for no account here and have no `sender`.
+pub fn execution_attributes(storage: &dyn Storage, caller: &Caller) -> StdResult<Vec<Attribute>> {
+    Ok(match caller {
+        Caller::External(sender) => vec![Attribute::new("sender", sender)],
+        Caller::IbcCounterparty { channel, sender } => vec![
+            Attribute::new("channel", channel),
+            Attribute::new("remote_sender", sender),
+        ],
+        Caller::Pipeline => match running_step(storage)? {
+            Some(step) => {
+                let mut attributes = vec![];
+                if let Some(sender) = PIPELINE_HISTORY.load(storage, step.pipeline_id)?.sender {
+                    attributes.push(Attribute::new("sender", sender));
+                }
+                attributes.push(Attribute::new("execution_id", step.pipeline_id.to_string()));
+                attributes.push(Attribute::new("step", step.step.to_string()));
+                attributes
+            }
+            None => vec![],
+        },
+    })
+}
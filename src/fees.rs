@@ -0,0 +1,256 @@
+use cosmwasm_std::{Addr, Coin, Deps, DepsMut, MessageInfo, Order, Response, StdResult, Storage, Uint128};
+
+use crate::admin::assert_admin;
+use crate::asset::{join_assets, Asset, AssetInfo};
+use crate::msg::CommandKind;
+use crate::state::{Config, Fee, ReferralMode, CONFIG, REFERRAL_BALANCES, RESERVED, TREASURY};
+use crate::ContractError;
+
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Fee charged for a command type, per-command fees take precedence over the default one
+pub fn command_fee(config: &Config, kind: CommandKind) -> Option<&Fee> {
+    config
+        .command_fees
+        .iter()
+        .find(|command_fee| command_fee.command == kind)
+        .map(|command_fee| &command_fee.fee)
+        .or(config.fee.as_ref())
+}
+
+/// Splits the command input into what is left for the command and the fee
+pub fn compute_fee(fee: Option<&Fee>, assets: Vec<Asset>) -> Result<(Vec<Asset>, Vec<Asset>), ContractError> {
+    let mut fees = vec![];
+    let mut remaining = vec![];
+    match fee {
+        None => return Ok((assets, fees)),
+        Some(Fee::Bps(bps)) => {
+            for mut asset in assets {
+                let amount = asset.amount.multiply_ratio(*bps as u128, BPS_DENOMINATOR);
+                if !amount.is_zero() {
+                    asset.amount -= amount;
+                    fees.push(Asset { info: asset.info.clone(), amount });
+                }
+                remaining.push(asset);
+            }
+        }
+        Some(Fee::Flat(fee)) => {
+            let mut charged = false;
+            for mut asset in assets {
+                if !charged && asset.info == fee.info && asset.amount >= fee.amount {
+                    asset.amount -= fee.amount;
+                    charged = true;
+                }
+                remaining.push(asset);
+            }
+            if !charged {
+                return Err(ContractError::InsufficientFee { fee: fee.to_string() });
+            }
+            fees.push(fee.clone());
+        }
+    }
+    remaining.retain(|asset| !asset.amount.is_zero());
+    Ok((remaining, fees))
+}
+
+/// Fails when a run spending `funds` could not pay the keeper tip and keep something to
+/// run with. A run spending nothing pays no tip.
+pub fn assert_covers_keeper_tip(config: &Config, funds: &[Asset]) -> Result<(), ContractError> {
+    if funds.is_empty() {
+        return Ok(());
+    }
+    let (remaining, _) = compute_fee(config.keeper_tip.as_ref(), funds.to_vec())?;
+    if remaining.is_empty() {
+        let fee = match &config.keeper_tip {
+            Some(Fee::Flat(tip)) => tip.to_string(),
+            _ => "keeper tip".to_string(),
+        };
+        return Err(ContractError::InsufficientFee { fee });
+    }
+    Ok(())
+}
+
+/// Splits the keeper tip off the funds of a run. A flat tip the run cannot pay, e.g.
+/// after the admin changed it, is skipped rather than leaving the position stuck.
+pub fn split_keeper_tip(config: &Config, funds: Vec<Asset>) -> Result<(Vec<Asset>, Vec<Asset>), ContractError> {
+    match compute_fee(config.keeper_tip.as_ref(), funds.clone()) {
+        Err(ContractError::InsufficientFee { .. }) => Ok((funds, vec![])),
+        split => split,
+    }
+}
+
+/// Fees taken from a command input
+#[derive(Default)]
+pub struct Charges {
+    pub protocol: Vec<Asset>,
+    pub referral: Vec<Asset>,
+}
+
+/// Splits the command input into what is left for the command, the protocol fee and
+/// the referrer share, without touching state
+pub fn compute_charges(config: &Config, kind: CommandKind, referral_bps: u16, assets: Vec<Asset>) -> Result<(Vec<Asset>, Charges), ContractError> {
+    // a command that spends nothing, like `ClaimRewards` or `Vote`, has nothing to pay from
+    if assets.is_empty() {
+        return Ok((assets, Charges::default()));
+    }
+    let (remaining, mut protocol) = compute_fee(command_fee(config, kind), assets)?;
+    if referral_bps == 0 {
+        return Ok((remaining, Charges { protocol, referral: vec![] }));
+    }
+
+    let referral_fee = Fee::Bps(referral_bps);
+    let (remaining, referral) = match config.referral_mode {
+        ReferralMode::FromFee => {
+            let (rest, referral) = compute_fee(Some(&referral_fee), protocol)?;
+            protocol = rest;
+            (remaining, referral)
+        }
+        ReferralMode::OnTop => compute_fee(Some(&referral_fee), remaining)?,
+    };
+    Ok((remaining, Charges { protocol, referral }))
+}
+
+/// Takes the protocol fee and the referrer share from the command input and keeps
+/// them until they are withdrawn or claimed
+pub fn charge_fee(storage: &mut dyn Storage, kind: CommandKind, referrer: Option<(&Addr, u16)>, assets: Vec<Asset>) -> Result<(Vec<Asset>, Charges), ContractError> {
+    let config = CONFIG.load(storage)?;
+    let referral_bps = referrer.map(|(_, bps)| bps).unwrap_or_default();
+    let (remaining, charges) = compute_charges(&config, kind, referral_bps, assets)?;
+
+    for fee in &charges.protocol {
+        TREASURY.update(storage, fee.info.to_string(), |accrued| add_asset(accrued, fee))?;
+        reserve(storage, fee)?;
+    }
+    if let Some((referrer, _)) = referrer {
+        for fee in &charges.referral {
+            REFERRAL_BALANCES.update(storage, (referrer, fee.info.to_string()), |balance| add_asset(balance, fee))?;
+            reserve(storage, fee)?;
+        }
+    }
+    Ok((remaining, charges))
+}
+
+fn add_asset(balance: Option<Asset>, asset: &Asset) -> StdResult<Asset> {
+    let mut balance = balance.unwrap_or_else(|| Asset {
+        info: asset.info.clone(),
+        amount: Uint128::zero(),
+    });
+    balance.amount += asset.amount;
+    Ok(balance)
+}
+
+/// Marks `asset` as held on behalf of someone so pipelines cannot spend it
+pub fn reserve(storage: &mut dyn Storage, asset: &Asset) -> StdResult<()> {
+    RESERVED.update(storage, asset.info.to_string(), |reserved| -> StdResult<_> {
+        Ok(reserved.unwrap_or_default() + asset.amount)
+    })?;
+    Ok(())
+}
+
+/// Releases a reserved `asset` once it leaves the contract or goes back to a pipeline
+pub fn release(storage: &mut dyn Storage, asset: &Asset) -> StdResult<()> {
+    let key = asset.info.to_string();
+    let reserved = RESERVED.may_load(storage, key.clone())?.unwrap_or_default();
+    let reserved = reserved.checked_sub(asset.amount)?;
+    if reserved.is_zero() {
+        RESERVED.remove(storage, key);
+    } else {
+        RESERVED.save(storage, key, &reserved)?;
+    }
+    Ok(())
+}
+
+pub fn accrued_fees(storage: &dyn Storage) -> StdResult<Vec<Asset>> {
+    TREASURY
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, asset)| asset))
+        .collect()
+}
+
+/// Native balance of the contract that is not reserved for the treasury or anyone else
+pub fn available_funds(deps: Deps, contract: &Addr) -> StdResult<Vec<Asset>> {
+    let mut funds = vec![];
+    for coin in deps.querier.query_all_balances(contract)? {
+        let reserved = RESERVED
+            .may_load(deps.storage, coin.denom.clone())?
+            .unwrap_or_default();
+        let amount = coin.amount.saturating_sub(reserved);
+        if !amount.is_zero() {
+            funds.push(Asset::native(coin.denom, amount));
+        }
+    }
+    Ok(funds)
+}
+
+/// Whether the unreserved native balance of the contract covers `coins`, so paying them
+/// out does not touch what it holds for others
+pub fn covers_available(deps: Deps, contract: &Addr, coins: &[Coin]) -> StdResult<bool> {
+    let available = available_funds(deps, contract)?;
+    Ok(coins.iter().all(|coin| {
+        available
+            .iter()
+            .any(|asset| asset.info == AssetInfo::Native(coin.denom.clone()) && asset.amount >= coin.amount)
+    }))
+}
+
+pub fn withdraw_fees(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: Option<AssetInfo>,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let config = assert_admin(deps.as_ref(), &info.sender)?;
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => config.admin,
+    };
+
+    let fees = match asset_info {
+        Some(asset_info) => TREASURY
+            .may_load(deps.storage, asset_info.to_string())?
+            .into_iter()
+            .collect(),
+        None => accrued_fees(deps.storage)?,
+    };
+    let mut msgs = vec![];
+    for fee in &fees {
+        TREASURY.remove(deps.storage, fee.info.to_string());
+        release(deps.storage, fee)?;
+        msgs.push(fee.transfer_msg(recipient.as_str())?);
+    }
+
+    let mut response = Response::new()
+        .add_attribute("method", "withdraw_fees")
+        .add_attribute("recipient", recipient);
+    // the chain refuses empty attribute values
+    if !fees.is_empty() {
+        response = response.add_attribute("amount", join_assets(&fees));
+    }
+    Ok(response.add_messages(msgs))
+}
+
+pub fn referral_balance(storage: &dyn Storage, referrer: &Addr) -> StdResult<Vec<Asset>> {
+    REFERRAL_BALANCES
+        .prefix(referrer)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, asset)| asset))
+        .collect()
+}
+
+pub fn claim_referral_fees(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let balance = referral_balance(deps.storage, &info.sender)?;
+    let mut msgs = vec![];
+    for asset in &balance {
+        REFERRAL_BALANCES.remove(deps.storage, (&info.sender, asset.info.to_string()));
+        release(deps.storage, asset)?;
+        msgs.push(asset.transfer_msg(info.sender.as_str())?);
+    }
+
+    let mut response = Response::new()
+        .add_attribute("method", "claim_referral_fees")
+        .add_attribute("referrer", info.sender);
+    if !balance.is_empty() {
+        response = response.add_attribute("amount", join_assets(&balance));
+    }
+    Ok(response.add_messages(msgs))
+}
@@ -0,0 +1,178 @@
+use cosmwasm_std::{Decimal, DepsMut, Env, GovMsg, Response, Uint128, VoteOption};
+
+use crate::asset::{assert_no_funds, Asset};
+use crate::events::command_event;
+use crate::history::originator;
+use crate::msg::{CommandKind, WeightedVoteOption};
+use crate::osmosis::stargate_msg;
+use crate::pipeline::Caller;
+use crate::proto::{self, MsgVoteWeighted, VOTE_WEIGHTED_TYPE_URL};
+use crate::staking::staked;
+use crate::state::{OptionTally, StakerVote, TALLIES, VOTES};
+use crate::ContractError;
+
+/// Each option at most once with a positive weight, the weights adding up to one
+pub fn validate_vote_options(options: &[WeightedVoteOption]) -> Result<(), ContractError> {
+    let invalid = |reason: &str| Err(ContractError::InvalidVote { reason: reason.to_string() });
+    for (i, option) in options.iter().enumerate() {
+        if option.weight.is_zero() {
+            return invalid("weights must be positive");
+        }
+        if options[..i].iter().any(|other| other.option == option.option) {
+            return invalid("options must not repeat");
+        }
+    }
+    if options.iter().map(|option| option.weight).fold(Decimal::zero(), |sum, weight| sum + weight) != Decimal::one() {
+        return invalid("weights must add up to one");
+    }
+    Ok(())
+}
+
+/// Records the vote of whoever started the pipeline and casts the votes of every staker
+/// on the proposal as one. Each counts with the stake they had when voting, a new vote
+/// only moves the tally by the difference to the one it replaces.
+pub fn execute_vote(
+    deps: DepsMut,
+    env: Env,
+    caller: Caller,
+    assets: Vec<Asset>,
+    kind: CommandKind,
+    proposal_id: u64,
+    options: Vec<WeightedVoteOption>,
+) -> Result<Response, ContractError> {
+    assert_no_funds(&assets)?;
+    validate_vote_options(&options)?;
+    let voter = originator(deps.storage, &caller)?.ok_or(ContractError::NoOriginator {})?;
+    let stake = staked(deps.storage, &voter)?;
+    if stake.is_zero() {
+        return Err(ContractError::NoStake {});
+    }
+
+    let mut tally = TALLIES.may_load(deps.storage, proposal_id)?.unwrap_or_default();
+    if let Some(previous) = VOTES.may_load(deps.storage, (proposal_id, &voter))? {
+        for option in previous.options {
+            if let Some(counted) = tally.iter_mut().find(|counted| counted.option == option.option) {
+                counted.stake = counted.stake.saturating_sub(previous.stake * option.weight);
+            }
+        }
+    }
+    for option in &options {
+        let amount = stake * option.weight;
+        match tally.iter_mut().find(|counted| counted.option == option.option) {
+            Some(counted) => counted.stake += amount,
+            None => tally.push(OptionTally {
+                option: option.option.clone(),
+                stake: amount,
+            }),
+        }
+    }
+    let combined = combine(&tally);
+    // a stake too small for any of its weights leaves nothing to cast
+    if combined.is_empty() {
+        return Err(ContractError::InvalidVote {
+            reason: "no stake is behind the vote".to_string(),
+        });
+    }
+    TALLIES.save(deps.storage, proposal_id, &tally)?;
+    VOTES.save(
+        deps.storage,
+        (proposal_id, &voter),
+        &StakerVote {
+            options: options.clone(),
+            stake,
+        },
+    )?;
+
+    let msg = match combined.as_slice() {
+        [single] => GovMsg::Vote {
+            proposal_id,
+            vote: single.option.clone(),
+        }
+        .into(),
+        _ => stargate_msg(
+            VOTE_WEIGHTED_TYPE_URL,
+            &MsgVoteWeighted {
+                proposal_id,
+                voter: env.contract.address.into(),
+                options: combined
+                    .iter()
+                    .map(|option| proto::WeightedVoteOption {
+                        option: vote_option_number(&option.option),
+                        weight: option.weight.atomics().to_string(),
+                    })
+                    .collect(),
+            },
+        ),
+    };
+
+    let describe = |options: &[WeightedVoteOption]| {
+        options
+            .iter()
+            .map(|option| format!("{}:{}", vote_option_name(&option.option), option.weight))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    Ok(Response::new()
+        .add_attribute("method", "execute_vote")
+        .add_event(
+            command_event(kind)
+                .add_attribute("proposal_id", proposal_id.to_string())
+                .add_attribute("voter", &voter)
+                .add_attribute("options", describe(&options))
+                .add_attribute("cast", describe(&combined)),
+        )
+        .add_message(msg))
+}
+
+/// Share of each option in the tally. The largest one takes the rounding remainder so
+/// the weights add up to exactly one, as the chain requires.
+fn combine(tally: &[OptionTally]) -> Vec<WeightedVoteOption> {
+    let tally: Vec<_> = tally
+        .iter()
+        .filter(|counted| !counted.stake.is_zero())
+        .map(|counted| (counted.option.clone(), counted.stake))
+        .collect();
+    let total: Uint128 = tally.iter().map(|(_, amount)| *amount).sum();
+    let largest = tally
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, amount))| *amount)
+        .map(|(i, _)| i)
+        .unwrap_or_default();
+
+    let mut options: Vec<WeightedVoteOption> = tally
+        .into_iter()
+        .map(|(option, amount)| WeightedVoteOption {
+            option,
+            weight: Decimal::from_ratio(amount, total),
+        })
+        .collect();
+    let others = options
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != largest)
+        .fold(Decimal::zero(), |sum, (_, option)| sum + option.weight);
+    if let Some(option) = options.get_mut(largest) {
+        option.weight = Decimal::one() - others;
+    }
+    options
+}
+
+/// `cosmos.gov.v1beta1.VoteOption`
+fn vote_option_number(option: &VoteOption) -> i32 {
+    match option {
+        VoteOption::Yes => 1,
+        VoteOption::Abstain => 2,
+        VoteOption::No => 3,
+        VoteOption::NoWithVeto => 4,
+    }
+}
+
+fn vote_option_name(option: &VoteOption) -> &'static str {
+    match option {
+        VoteOption::Yes => "yes",
+        VoteOption::Abstain => "abstain",
+        VoteOption::No => "no",
+        VoteOption::NoWithVeto => "no_with_veto",
+    }
+}
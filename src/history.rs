@@ -0,0 +1,207 @@
+use cosmwasm_std::{Addr, Env, Order, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+use crate::asset::Asset;
+use crate::msg::{CommandKind, ExecuteMsg, PacketOrigin};
+use crate::pipeline::Caller;
+use crate::state::{
+    PipelineRecord, PipelineStatus, StepRecord, StepStatus, DEFAULT_LIMIT, MAX_LIMIT, PIPELINES,
+    PIPELINES_BY_SENDER, PIPELINE_COUNT, PIPELINE_HISTORY,
+};
+
+/// Opens the record of a pipeline about to start, returns its id
+pub fn record_pipeline(
+    storage: &mut dyn Storage,
+    env: &Env,
+    caller: &Caller,
+    contract_addr: &Addr,
+    commands: &[ExecuteMsg],
+    funds: &[Asset],
+) -> StdResult<u64> {
+    let id = PIPELINE_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    PIPELINE_COUNT.save(storage, &id)?;
+
+    // a nested hop runs for whoever started the pipeline it belongs to
+    let parent = match caller {
+        Caller::Pipeline => match PIPELINES.may_load(storage)?.unwrap_or_default().last() {
+            Some(pipeline) => PIPELINE_HISTORY.may_load(storage, pipeline.id)?,
+            None => None,
+        },
+        _ => None,
+    };
+    let (sender, channel, remote_sender) = match (caller, &parent) {
+        (Caller::External(addr), _) => (Some(addr.clone()), None, None),
+        (Caller::IbcCounterparty { channel, sender }, _) => (None, Some(channel.clone()), Some(sender.clone())),
+        (Caller::Pipeline, Some(parent)) => (parent.sender.clone(), parent.channel.clone(), parent.remote_sender.clone()),
+        (Caller::Pipeline, None) => (None, None, None),
+    };
+
+    let record = PipelineRecord {
+        id,
+        parent_id: parent.map(|parent| parent.id),
+        sender,
+        channel,
+        remote_sender,
+        contract_addr: contract_addr.clone(),
+        commands: commands.to_vec(),
+        funds: funds.to_vec(),
+        steps: vec![],
+        status: PipelineStatus::Running,
+        started_at: env.block.time,
+        finished_at: None,
+    };
+    PIPELINE_HISTORY.save(storage, id, &record)?;
+    if let Some(sender) = &record.sender {
+        PIPELINES_BY_SENDER.save(storage, (sender, id), &())?;
+    }
+    Ok(id)
+}
+
+/// Loads, changes and saves the record of pipeline `id`, pipelines started before
+/// history was kept have none
+fn update_record<F>(storage: &mut dyn Storage, id: u64, update: F) -> StdResult<()>
+where
+    F: FnOnce(&mut PipelineRecord),
+{
+    if let Some(mut record) = PIPELINE_HISTORY.may_load(storage, id)? {
+        update(&mut record);
+        PIPELINE_HISTORY.save(storage, id, &record)?;
+    }
+    Ok(())
+}
+
+pub fn record_step(storage: &mut dyn Storage, env: &Env, id: u64, command: CommandKind, input: Vec<Asset>) -> StdResult<()> {
+    update_record(storage, id, |record| {
+        record.steps.push(StepRecord {
+            command,
+            status: StepStatus::Running,
+            input,
+            output: vec![],
+            ibc_sequence: None,
+            error: None,
+            started_at: env.block.time,
+            finished_at: None,
+        })
+    })
+}
+
+/// Closes the running step of pipeline `id`, returns its index and command
+pub fn finish_step(storage: &mut dyn Storage, env: &Env, id: u64, output: Vec<Asset>) -> StdResult<Option<(u32, CommandKind)>> {
+    let mut finished = None;
+    update_record(storage, id, |record| {
+        let index = record.steps.len() as u32;
+        if let Some(step) = record.steps.last_mut() {
+            finished = Some((index - 1, step.command));
+            // a hop packet is only done once the counterparty acknowledges it
+            step.status = match step.command {
+                CommandKind::IbcContractHop => StepStatus::PacketSent,
+                _ => StepStatus::Succeeded,
+            };
+            step.output = output;
+            step.finished_at = Some(env.block.time);
+        }
+    })?;
+    Ok(finished)
+}
+
+/// Marks the running step of pipeline `id` as failed, returns its index and command
+pub fn fail_step(storage: &mut dyn Storage, env: &Env, id: u64, error: String) -> StdResult<Option<(u32, CommandKind)>> {
+    let mut failed = None;
+    update_record(storage, id, |record| {
+        let index = record.steps.len() as u32;
+        if let Some(step) = record.steps.last_mut() {
+            failed = Some((index - 1, step.command));
+            step.status = StepStatus::Failed;
+            step.error = Some(error);
+            step.finished_at = Some(env.block.time);
+        }
+    })?;
+    Ok(failed)
+}
+
+pub fn finish_pipeline(storage: &mut dyn Storage, env: &Env, id: u64) -> StdResult<()> {
+    update_record(storage, id, |record| {
+        record.status = PipelineStatus::Completed;
+        record.finished_at = Some(env.block.time);
+    })
+}
+
+/// Running step of the innermost pipeline, where a packet sent now comes from
+pub fn running_step(storage: &dyn Storage) -> StdResult<Option<PacketOrigin>> {
+    let pipeline = match PIPELINES.may_load(storage)?.unwrap_or_default().pop() {
+        Some(pipeline) => pipeline,
+        None => return Ok(None),
+    };
+    Ok(PIPELINE_HISTORY
+        .may_load(storage, pipeline.id)?
+        .filter(|record| !record.steps.is_empty())
+        .map(|record| PacketOrigin {
+            pipeline_id: record.id,
+            step: record.steps.len() as u32 - 1,
+        }))
+}
+
+/// Account a command runs for: its sender, or whoever started the running pipeline.
+/// Commands that came over IBC run for no account.
+pub fn originator(storage: &dyn Storage, caller: &Caller) -> StdResult<Option<Addr>> {
+    Ok(match caller {
+        Caller::External(sender) => Some(sender.clone()),
+        Caller::IbcCounterparty { .. } => None,
+        Caller::Pipeline => match PIPELINES.may_load(storage)?.unwrap_or_default().last() {
+            Some(pipeline) => PIPELINE_HISTORY
+                .may_load(storage, pipeline.id)?
+                .and_then(|record| record.sender),
+            None => None,
+        },
+    })
+}
+
+/// Stores how the packet sent by a pipeline step ended
+pub fn record_packet_result(
+    storage: &mut dyn Storage,
+    env: &Env,
+    origin: &PacketOrigin,
+    sequence: u64,
+    status: StepStatus,
+    error: Option<String>,
+) -> StdResult<()> {
+    update_record(storage, origin.pipeline_id, |record| {
+        if let Some(step) = record.steps.get_mut(origin.step as usize) {
+            step.status = status;
+            step.ibc_sequence = Some(sequence);
+            step.error = error;
+            step.finished_at = Some(env.block.time);
+        }
+    })
+}
+
+pub fn pipelines_by_sender(
+    storage: &dyn Storage,
+    sender: &Addr,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<PipelineRecord>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    PIPELINES_BY_SENDER
+        .prefix(sender)
+        .keys(storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|id| PIPELINE_HISTORY.load(storage, id?))
+        .collect()
+}
+
+/// Who a packet sent for `caller` runs for on the counterparty: the account the command
+/// runs for, or the remote sender of a pipeline received over IBC
+pub fn packet_sender(storage: &dyn Storage, caller: &Caller) -> StdResult<Option<String>> {
+    Ok(match caller {
+        Caller::External(sender) => Some(sender.to_string()),
+        Caller::IbcCounterparty { sender, .. } => Some(sender.clone()),
+        Caller::Pipeline => match PIPELINES.may_load(storage)?.unwrap_or_default().last() {
+            Some(pipeline) => PIPELINE_HISTORY
+                .may_load(storage, pipeline.id)?
+                .and_then(|record| record.sender.map(String::from).or(record.remote_sender)),
+            None => None,
+        },
+    })
+}
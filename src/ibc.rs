@@ -2,17 +2,26 @@
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{from_binary, DepsMut, Env, IbcBasicResponse, IbcChannel, IbcChannelCloseMsg,
                    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcOrder, IbcPacketAckMsg,
-                   IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse, WasmMsg, to_binary};
+                   IbcPacket, IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse, Coin, Event};
 
 use crate::{
-    ack::{make_ack_fail, make_ack_success},
+    ack::{make_ack_fail, make_ack_success, Ack},
     error::Never,
     msg::IbcExecuteMsg,
     state::CONNECTION_COUNTS,
     ContractError,
 };
-// use crate::contract::contract_hop;
+use crate::admin::assert_ibc_receive_not_paused;
+use crate::asset::{deduct, Asset};
+use crate::events::{asset_attributes, event};
+use crate::fees::{charge_fee, compute_charges};
+use crate::history::record_packet_result;
+use crate::msg::{CommandKind, PacketOrigin};
+use crate::pipeline::{assert_can_start, start_pipeline, Caller};
+use crate::remote::{debit_remote, receive_withdraw_remote, remote_balance};
+use crate::state::{StepStatus, CONFIG};
 use crate::msg::ExecuteMsg;
+use crate::validation::{validate_commands, validate_packet_size};
 
 pub const IBC_VERSION: &str = "blazarbit-1";
 
@@ -77,59 +86,120 @@ pub fn do_ibc_packet_receive(
     env: Env,
     msg: IbcPacketReceiveMsg,
 ) -> Result<IbcReceiveResponse, ContractError> {
+    // Failing here writes an error ack, so the sender learns the packet was refused
+    assert_ibc_receive_not_paused(deps.storage)?;
+
     // The channel this packet is being relayed along on this chain.
+    // Only counterparties on allowlisted channels may start pipelines here.
+    let channel = msg.packet.dest.channel_id;
+    let config = CONFIG.load(deps.storage)?;
+    if !config.allowed_channels.contains(&channel) {
+        return Err(ContractError::ChannelNotAllowed { channel });
+    }
+    validate_packet_size(&config, &msg.packet.data)?;
+
     let msg: IbcExecuteMsg = from_binary(&msg.packet.data)?;
     match msg {
-        IbcExecuteMsg::IbcContractHop { commands } => receive_ibc_contract_hop(deps, env.contract.address.into(), commands),
+        IbcExecuteMsg::IbcContractHop { commands, funds, sender, .. } => {
+            // leftovers go back to the remote account of the sender, a packet without one
+            // would leave them to nobody
+            let sender = sender.ok_or(ContractError::UnknownRemoteSender {})?;
+            validate_commands(deps.as_ref(), &env, &commands)?;
+            receive_ibc_contract_hop(deps, env, Caller::IbcCounterparty { channel, sender }, commands, funds)
+        }
+        IbcExecuteMsg::WithdrawRemote { funds, sender, recipient } => receive_withdraw_remote(deps, channel, sender, recipient, funds),
     }
 }
 
 pub fn receive_ibc_contract_hop(
-    deps: DepsMut,
-    // info: MessageInfo,
-    self_address: String,
+    mut deps: DepsMut,
+    env: Env,
+    caller: Caller,
     commands: Vec<ExecuteMsg>,
+    funds: Vec<Coin>,
 ) -> Result<IbcReceiveResponse, ContractError> {
-    // contract_hop(deps, info, self_address, commands)?;
-    // Ok(IbcReceiveResponse::new())
-    let msg = WasmMsg::Execute {
-        contract_addr: self_address.clone(),
-        msg: to_binary(&ExecuteMsg::ContractHop { contract_addr: self_address.clone(), commands }).unwrap(),
-        funds: deps.querier.query_all_balances(self_address.clone())?,
+    let (channel, sender) = match &caller {
+        Caller::IbcCounterparty { channel, sender } => (channel.clone(), sender.clone()),
+        _ => return Err(ContractError::Unauthorized {}),
     };
+    // the counterparty only spends what its sender deposited here. An error ack keeps
+    // what was written before it, so everything that may fail is checked before the
+    // funds are taken.
+    let funds: Vec<Asset> = funds.into_iter().map(Asset::from).collect();
+    deduct(&remote_balance(deps.storage, &channel, &sender)?, &funds)?;
+    let (remaining, _) = compute_charges(&CONFIG.load(deps.storage)?, CommandKind::ContractHop, 0, funds.clone())?;
+    assert_can_start(deps.as_ref(), &env, commands.clone(), remaining)?;
+    debit_remote(deps.storage, &channel, &sender, &funds)?;
+    let (funds, _) = charge_fee(deps.storage, CommandKind::ContractHop, None, funds)?;
+
+    let mut event = event("ibc_receive")
+        .add_attribute("channel", &channel)
+        .add_attribute("remote_sender", &sender)
+        .add_attribute("commands", commands.len().to_string())
+        .add_attributes(asset_attributes("", &funds));
+    let (id, msgs) = start_pipeline(deps.branch(), &env, env.contract.address.clone(), commands, funds, &caller, caller.beneficiary())?;
+    if let Some(id) = id {
+        event = event.add_attribute("pipeline_id", id.to_string());
+    }
     Ok(IbcReceiveResponse::new()
-        .add_attribute("method", "execute_increment")
-        // .add_attribute("count", count.to_string())
-        .add_message(msg)
+        .add_attribute("method", "receive_ibc_contract_hop")
+        .add_attribute("channel", channel)
+        .add_event(event)
+        .add_submessages(msgs)
         .set_ack(make_ack_success()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_packet_ack(
-    _deps: DepsMut,
-    _env: Env,
-    _ack: IbcPacketAckMsg,
+    deps: DepsMut,
+    env: Env,
+    ack: IbcPacketAckMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
-    // Nothing to do here. We don't keep any state about the other
-    // chain, just deliver messages so nothing to update.
-    //
-    // If we did care about how the other chain received our message
-    // we could deserialize the data field into an `Ack` and inspect
-    // it.
-    Ok(IbcBasicResponse::new().add_attribute("method", "ibc_packet_ack"))
+    // Only packets sent by a pipeline step have a history record to update
+    let packet = ack.original_packet;
+    let mut response = IbcBasicResponse::new().add_attribute("method", "ibc_packet_ack");
+    if let Some(origin) = packet_origin_of(&packet) {
+        // an ack we cannot read must not keep the relayer from delivering it
+        let (status, error) = match from_binary(&ack.acknowledgement.data) {
+            Ok(Ack::Result(_)) => (StepStatus::Acknowledged, None),
+            Ok(Ack::Error(error)) => (StepStatus::Failed, Some(error)),
+            Err(err) => (StepStatus::Failed, Some(err.to_string())),
+        };
+        record_packet_result(deps.storage, &env, &origin, packet.sequence, status, error)?;
+        response = response.add_event(packet_event(&origin, packet.sequence, status));
+    }
+    Ok(response)
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn ibc_packet_timeout(
-    _deps: DepsMut,
-    _env: Env,
-    _msg: IbcPacketTimeoutMsg,
+    deps: DepsMut,
+    env: Env,
+    msg: IbcPacketTimeoutMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
-    // As with ack above, nothing to do here. If we cared about
-    // keeping track of state between the two chains then we'd want to
-    // respond to this likely as it means that the packet in question
-    // isn't going anywhere.
-    Ok(IbcBasicResponse::new().add_attribute("method", "ibc_packet_timeout"))
+    let packet = msg.packet;
+    let mut response = IbcBasicResponse::new().add_attribute("method", "ibc_packet_timeout");
+    if let Some(origin) = packet_origin_of(&packet) {
+        record_packet_result(deps.storage, &env, &origin, packet.sequence, StepStatus::TimedOut, None)?;
+        response = response.add_event(packet_event(&origin, packet.sequence, StepStatus::TimedOut));
+    }
+    Ok(response)
+}
+
+fn packet_event(origin: &PacketOrigin, sequence: u64, status: StepStatus) -> Event {
+    event("packet_result")
+        .add_attribute("execution_id", origin.pipeline_id.to_string())
+        .add_attribute("step", origin.step.to_string())
+        .add_attribute("sequence", sequence.to_string())
+        .add_attribute("status", status.as_str())
+}
+
+/// Pipeline step a packet we sent came from
+fn packet_origin_of(packet: &IbcPacket) -> Option<PacketOrigin> {
+    match from_binary(&packet.data).ok()? {
+        IbcExecuteMsg::IbcContractHop { origin, .. } => origin,
+        IbcExecuteMsg::WithdrawRemote { .. } => None,
+    }
 }
 
 pub fn validate_order_and_version(
@@ -0,0 +1,127 @@
+use cosmwasm_std::{Coin, DepsMut, Env, Order, StdResult, Storage, Timestamp, Uint128};
+use cw_storage_plus::Bound;
+
+use crate::asset::{Asset, AssetInfo};
+use crate::fees::{available_funds, release, reserve};
+use crate::state::{SettleBlock, INCOMING, PIPELINES, SETTLED, SETTLE_BLOCK};
+
+/// Most due entries a single call settles, claims wait for the rest
+const SETTLE_LIMIT: usize = 10;
+
+/// Records coins the chain pays back to the contract at `due`, `source` names what they
+/// come from, e.g. `unbonding-4` or `lock-7`
+pub fn expect_incoming(
+    storage: &mut dyn Storage,
+    due: Timestamp,
+    source: &str,
+    coins: &[Coin],
+) -> StdResult<()> {
+    INCOMING.save(storage, (due.seconds(), source), &coins.to_vec())
+}
+
+/// Reserves the coins that came back for their owner, so no other claim pays out of
+/// them. Entries due before this block settle in full when the unreserved balance covers
+/// them all. Otherwise some came back short, e.g. slashed unbondings, and those surely
+/// back share what is there pro rata, the others wait for a later block.
+pub fn settle_incoming(deps: DepsMut, env: &Env) -> StdResult<()> {
+    // a running step counts any change of the reserved funds as its own
+    if !PIPELINES
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .is_empty()
+    {
+        return Ok(());
+    }
+    let paid_until = paid_until(deps.storage, env)?;
+    // coins due at this block time are only paid at its end
+    let end = Bound::exclusive((env.block.time.seconds(), ""));
+    let mut due = INCOMING
+        .range(deps.storage, None, Some(end), Order::Ascending)
+        .take(SETTLE_LIMIT)
+        .collect::<StdResult<Vec<_>>>()?;
+    let available = available_funds(deps.as_ref(), &env.contract.address)?;
+    if !covers(&available, &due) {
+        due.retain(|((seconds, _), _)| *seconds <= paid_until.seconds());
+    }
+
+    let totals = totals(&due);
+    for ((seconds, source), coins) in due {
+        let settled: Vec<Coin> = coins
+            .into_iter()
+            .map(|coin| {
+                let total = amount_of(&totals, &coin.denom);
+                let held = amount_of(&available, &coin.denom).min(total);
+                Coin {
+                    amount: coin.amount.multiply_ratio(held, total),
+                    denom: coin.denom,
+                }
+            })
+            .filter(|coin| !coin.amount.is_zero())
+            .collect();
+        for coin in &settled {
+            reserve(deps.storage, &Asset::from(coin.clone()))?;
+        }
+        INCOMING.remove(deps.storage, (seconds, &source));
+        SETTLED.save(deps.storage, (seconds, &source), &settled)?;
+    }
+    Ok(())
+}
+
+/// Time by which everything due surely came back, moves on with each new block settled in
+fn paid_until(storage: &mut dyn Storage, env: &Env) -> StdResult<Timestamp> {
+    let block = match SETTLE_BLOCK.may_load(storage)? {
+        Some(block) if block.height == env.block.height => return Ok(block.paid_until),
+        Some(block) => SettleBlock {
+            height: env.block.height,
+            time: env.block.time,
+            paid_until: block.time,
+        },
+        None => SettleBlock {
+            height: env.block.height,
+            time: env.block.time,
+            paid_until: Timestamp::from_seconds(0),
+        },
+    };
+    SETTLE_BLOCK.save(storage, &block)?;
+    Ok(block.paid_until)
+}
+
+fn covers(available: &[Asset], due: &[((u64, String), Vec<Coin>)]) -> bool {
+    totals(due)
+        .iter()
+        .all(|total| amount_of(available, &total.info.to_string()) >= total.amount)
+}
+
+/// What `due` adds up to per denom
+fn totals(due: &[((u64, String), Vec<Coin>)]) -> Vec<Asset> {
+    let mut totals: Vec<Asset> = vec![];
+    for coin in due.iter().flat_map(|(_, coins)| coins) {
+        match totals.iter_mut().find(|total| total.info == AssetInfo::Native(coin.denom.clone())) {
+            Some(total) => total.amount += coin.amount,
+            None => totals.push(Asset::from(coin.clone())),
+        }
+    }
+    totals
+}
+
+fn amount_of(assets: &[Asset], denom: &str) -> Uint128 {
+    assets
+        .iter()
+        .find(|asset| asset.info == AssetInfo::Native(denom.to_string()))
+        .map(|asset| asset.amount)
+        .unwrap_or_default()
+}
+
+/// Releases the coins settled for an entry for their owner to take, none while they are
+/// not back
+pub fn take_incoming(storage: &mut dyn Storage, due: Timestamp, source: &str) -> StdResult<Option<Vec<Coin>>> {
+    let coins = match SETTLED.may_load(storage, (due.seconds(), source))? {
+        Some(coins) => coins,
+        None => return Ok(None),
+    };
+    SETTLED.remove(storage, (due.seconds(), source));
+    for coin in &coins {
+        release(storage, &Asset::from(coin.clone()))?;
+    }
+    Ok(Some(coins))
+}
@@ -1,9 +1,29 @@
+pub mod admin;
+pub mod asset;
+pub mod condition;
 pub mod contract;
+pub mod dca;
 mod error;
+pub mod events;
+pub mod fees;
+pub mod gov;
 pub mod helpers;
+pub mod history;
+pub mod incoming;
 pub mod msg;
+pub mod osmosis;
+pub mod pipeline;
+pub mod schedule;
+pub mod simulation;
+pub mod staking;
 pub mod state;
 pub mod ibc;
+pub mod limit_order;
+pub mod lockup;
+pub mod proto;
+pub mod receipt;
+pub mod remote;
 pub mod ack;
+pub mod validation;
 
 pub use crate::error::ContractError;
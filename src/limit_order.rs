@@ -0,0 +1,193 @@
+use cosmwasm_std::{Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage, Uint128};
+use cw20::Expiration;
+use cw_storage_plus::Bound;
+
+use crate::admin::assert_not_paused;
+use crate::asset::Asset;
+use crate::events::{asset_attributes, event};
+use crate::fees::{assert_covers_keeper_tip, compute_charges, release, reserve, split_keeper_tip};
+use crate::msg::{CommandKind, ExecuteMsg};
+use crate::osmosis::spot_price;
+use crate::pipeline::{start_pipeline, Caller};
+use crate::receipt::{assert_no_receipt, credit_receipt, position_beneficiary};
+use crate::state::{Beneficiary, Config, LimitOrder, Position, CONFIG, DEFAULT_LIMIT, LIMIT_ORDERS, LIMIT_ORDER_COUNT, MAX_LIMIT};
+use crate::validation::validate_command;
+use crate::ContractError;
+
+/// The `Swap` filling `order`, it fails on chain if the pool pays less than `min_price`
+/// for what is left of `offer` after the swap fee
+fn fill_command(config: &Config, order: &LimitOrder, offer: Asset) -> Result<ExecuteMsg, ContractError> {
+    let (swapped, _) = compute_charges(config, CommandKind::Swap, 0, vec![offer.clone()])?;
+    let swapped = swapped.iter().map(|asset| asset.amount).sum::<Uint128>();
+    Ok(ExecuteMsg::Swap {
+        pool_id: order.pool_id,
+        token_out_denom: order.token_out_denom.clone(),
+        token_out_min_amount: swapped * order.min_price,
+        asset: Some(offer),
+        referrer: None,
+    })
+}
+
+/// Fields of `ExecuteMsg::PlaceLimitOrder`
+pub struct OrderTerms {
+    pub pool_id: u64,
+    pub token_out_denom: String,
+    pub min_price: Decimal,
+    pub expiry: Expiration,
+}
+
+pub fn place_limit_order(deps: DepsMut, env: Env, caller: Caller, funds: Vec<Asset>, terms: OrderTerms) -> Result<Response, ContractError> {
+    let OrderTerms {
+        pool_id,
+        token_out_denom,
+        min_price,
+        expiry,
+    } = terms;
+    let owner = caller.refund_address().ok_or(ContractError::Unauthorized {})?;
+    let offer = match funds.as_slice() {
+        [offer] => offer.clone(),
+        _ => return Err(ContractError::SingleAssetRequired {}),
+    };
+    // swaps only take native coins
+    offer.to_coin()?;
+    if expiry.is_expired(&env.block) {
+        return Err(ContractError::ExpiryPast {});
+    }
+
+    let id = LIMIT_ORDER_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    let order = LimitOrder {
+        id,
+        owner,
+        pool_id,
+        offer,
+        token_out_denom,
+        min_price,
+        expiry,
+        created_at: env.block.time,
+    };
+    // the fill must pass the same checks as a swap sent right away
+    let config = CONFIG.load(deps.storage)?;
+    assert_covers_keeper_tip(&config, std::slice::from_ref(&order.offer))?;
+    let swap = fill_command(&config, &order, order.offer.clone())?;
+    assert_not_paused(deps.storage, &swap)?;
+    validate_command(deps.as_ref(), &env, &swap)?;
+
+    LIMIT_ORDER_COUNT.save(deps.storage, &id)?;
+    reserve(deps.storage, &order.offer)?;
+    LIMIT_ORDERS.save(deps.storage, id, &order)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "place_limit_order")
+        .add_attribute("id", id.to_string())
+        .add_event(
+            event("place_limit_order")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", &order.owner)
+                .add_attribute("pool_id", pool_id.to_string())
+                .add_attribute("token_out_denom", &order.token_out_denom)
+                .add_attribute("min_price", min_price.to_string())
+                .add_attributes(asset_attributes("", std::slice::from_ref(&order.offer))),
+        ))
+}
+
+/// Removes a limit order and releases its escrow
+fn take_order(storage: &mut dyn Storage, id: u64) -> StdResult<LimitOrder> {
+    let order = LIMIT_ORDERS.load(storage, id)?;
+    LIMIT_ORDERS.remove(storage, id);
+    release(storage, &order.offer)?;
+    Ok(order)
+}
+
+pub fn fill_limit_order(deps: DepsMut, env: Env, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let order = LIMIT_ORDERS.load(deps.storage, id)?;
+    if order.expiry.is_expired(&env.block) {
+        return Err(ContractError::OrderExpired { id });
+    }
+    let offer_denom = order.offer.info.to_string();
+    let price = spot_price(deps.as_ref(), order.pool_id, &offer_denom, &order.token_out_denom)?;
+    if price < order.min_price {
+        return Err(ContractError::PriceNotReached {
+            id,
+            min_price: order.min_price,
+            price,
+        });
+    }
+    let order = take_order(deps.storage, id)?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let (funds, tip) = split_keeper_tip(&config, vec![order.offer.clone()])?;
+    let tip_msgs = tip
+        .iter()
+        .map(|asset| asset.transfer_msg(info.sender.as_str()))
+        .collect::<StdResult<Vec<_>>>()?;
+    let offer = funds
+        .first()
+        .cloned()
+        .ok_or_else(|| ContractError::ZeroAmount { field: "offer".to_string() })?;
+
+    // a one step pipeline running for the owner, who gets the swap output back at its end
+    // unless a receipt of the order circulates
+    let swap = fill_command(&config, &order, offer)?;
+    validate_command(deps.as_ref(), &env, &swap)?;
+    let caller = Caller::External(order.owner.clone());
+    let beneficiary = position_beneficiary(deps.storage, &Position::LimitOrder(id), &order.owner)?;
+    let (pipeline_id, msgs) = start_pipeline(deps, &env, env.contract.address.clone(), vec![swap], funds, &caller, beneficiary)?;
+    let mut event = event("fill_limit_order")
+        .add_attribute("id", id.to_string())
+        .add_attribute("owner", &order.owner)
+        .add_attribute("keeper", &info.sender)
+        .add_attribute("price", price.to_string())
+        .add_attributes(asset_attributes("tip_", &tip));
+    if let Some(pipeline_id) = pipeline_id {
+        event = event.add_attribute("pipeline_id", pipeline_id.to_string());
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "fill_limit_order")
+        .add_attribute("id", id.to_string())
+        .add_event(event)
+        .add_submessages(msgs)
+        .add_messages(tip_msgs))
+}
+
+pub fn cancel_limit_order(deps: DepsMut, env: Env, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let order = LIMIT_ORDERS.load(deps.storage, id)?;
+    let expired = order.expiry.is_expired(&env.block);
+    if order.owner != info.sender && !expired {
+        return Err(ContractError::Unauthorized {});
+    }
+    // the owner may not take back an order whose receipt circulates, once it expired
+    // the refund goes to whoever redeems the receipt
+    let position = Position::LimitOrder(id);
+    if !expired {
+        assert_no_receipt(deps.storage, &position)?;
+    }
+    let beneficiary = position_beneficiary(deps.storage, &position, &order.owner)?;
+    let order = take_order(deps.storage, id)?;
+    let mut msgs = vec![];
+    match &beneficiary {
+        Beneficiary::Receipt(subdenom) => credit_receipt(deps.storage, subdenom, std::slice::from_ref(&order.offer))?,
+        _ => msgs.push(order.offer.transfer_msg(order.owner.as_str())?),
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "cancel_limit_order")
+        .add_attribute("id", id.to_string())
+        .add_event(
+            event("cancel_limit_order")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", &order.owner)
+                .add_attributes(asset_attributes("", std::slice::from_ref(&order.offer))),
+        )
+        .add_messages(msgs))
+}
+
+pub fn limit_orders(deps: Deps, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Vec<LimitOrder>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    LIMIT_ORDERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, order)| order))
+        .collect()
+}
@@ -0,0 +1,206 @@
+use cosmwasm_std::{BankMsg, DepsMut, Env, MessageInfo, Response, StdError, SubMsg, SubMsgResult};
+use prost::Message;
+
+use crate::asset::{assert_no_funds, Asset};
+use crate::events::{asset_attributes, command_event, event};
+use crate::history::originator;
+use crate::incoming::{expect_incoming, take_incoming};
+use crate::msg::CommandKind;
+use crate::osmosis::{proto_coin, stargate_msg};
+use crate::pipeline::Caller;
+use crate::proto::{
+    Duration, MsgBeginUnlocking, MsgLockAndSuperfluidDelegate, MsgLockTokens, MsgLockTokensResponse,
+    MsgSuperfluidUnbondLock, MsgSuperfluidUndelegate, BEGIN_UNLOCKING_TYPE_URL,
+    LOCK_AND_SUPERFLUID_DELEGATE_TYPE_URL, LOCK_TOKENS_TYPE_URL, SUPERFLUID_UNBOND_LOCK_TYPE_URL,
+    SUPERFLUID_UNDELEGATE_TYPE_URL,
+};
+use crate::state::{Lock, LOCKS, PENDING_LOCK};
+use crate::ContractError;
+
+pub const LOCK_REPLY_ID: u64 = 2;
+/// Superfluid only takes locks of the longest Osmosis lock duration, two weeks
+pub const SUPERFLUID_LOCK_DURATION: u64 = 14 * 24 * 60 * 60;
+
+/// Keeps the lock about to be made for the reply that brings its id
+fn pending_lock(
+    deps: DepsMut,
+    env: &Env,
+    caller: &Caller,
+    assets: &[Asset],
+    duration: u64,
+    validator: Option<String>,
+) -> Result<Lock, ContractError> {
+    let owner = originator(deps.storage, caller)?.ok_or(ContractError::NoOriginator {})?;
+    let coins = assets.iter().map(Asset::to_coin).collect::<Result<Vec<_>, _>>()?;
+    if coins.is_empty() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "you must send the coins you wish to use",
+        )));
+    }
+    let lock = Lock {
+        id: 0,
+        owner,
+        coins,
+        duration,
+        validator,
+        created_at: env.block.time,
+        unlocks_at: None,
+    };
+    PENDING_LOCK.save(deps.storage, &lock)?;
+    Ok(lock)
+}
+
+pub fn execute_lock_tokens(deps: DepsMut, env: Env, caller: Caller, assets: Vec<Asset>, duration: u64) -> Result<Response, ContractError> {
+    let lock = pending_lock(deps, &env, &caller, &assets, duration, None)?;
+    let event = command_event(CommandKind::LockTokens)
+        .add_attribute("duration", duration.to_string())
+        .add_attribute("owner", &lock.owner)
+        .add_attributes(asset_attributes("", &assets));
+
+    let msg = stargate_msg(
+        LOCK_TOKENS_TYPE_URL,
+        &MsgLockTokens {
+            owner: env.contract.address.into(),
+            duration: Some(Duration {
+                seconds: duration as i64,
+                nanos: 0,
+            }),
+            coins: lock.coins.iter().map(proto_coin).collect(),
+        },
+    );
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_lock_tokens")
+        .add_event(event)
+        .add_submessage(SubMsg::reply_on_success(msg, LOCK_REPLY_ID)))
+}
+
+pub fn execute_superfluid_delegate(deps: DepsMut, env: Env, caller: Caller, assets: Vec<Asset>, validator: String) -> Result<Response, ContractError> {
+    let lock = pending_lock(deps, &env, &caller, &assets, SUPERFLUID_LOCK_DURATION, Some(validator.clone()))?;
+    let event = command_event(CommandKind::SuperfluidDelegate)
+        .add_attribute("validator", &validator)
+        .add_attribute("owner", &lock.owner)
+        .add_attributes(asset_attributes("", &assets));
+
+    let msg = stargate_msg(
+        LOCK_AND_SUPERFLUID_DELEGATE_TYPE_URL,
+        &MsgLockAndSuperfluidDelegate {
+            sender: env.contract.address.into(),
+            coins: lock.coins.iter().map(proto_coin).collect(),
+            val_addr: validator,
+        },
+    );
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_superfluid_delegate")
+        .add_event(event)
+        .add_submessage(SubMsg::reply_on_success(msg, LOCK_REPLY_ID)))
+}
+
+/// Both lock messages answer with the id of the new lock
+pub fn lock_reply(deps: DepsMut, result: SubMsgResult) -> Result<Response, ContractError> {
+    let data = result
+        .into_result()
+        .map_err(StdError::generic_err)?
+        .data
+        .ok_or_else(|| StdError::generic_err("lock reply carries no lock id"))?;
+    let id = MsgLockTokensResponse::decode(data.as_slice())
+        .map_err(|err| StdError::parse_err("MsgLockTokensResponse", err))?
+        .id;
+
+    let mut lock = PENDING_LOCK.load(deps.storage)?;
+    PENDING_LOCK.remove(deps.storage);
+    lock.id = id;
+    LOCKS.save(deps.storage, id, &lock)?;
+
+    let coins: Vec<Asset> = lock.coins.iter().cloned().map(Asset::from).collect();
+    Ok(Response::new()
+        .add_attribute("method", "lock_reply")
+        .add_event(
+            event("lock_created")
+                .add_attribute("lock_id", id.to_string())
+                .add_attribute("owner", &lock.owner)
+                .add_attributes(asset_attributes("", &coins)),
+        ))
+}
+
+/// Only whoever the lock belongs to may unlock it, superfluid locks are undelegated first
+pub fn execute_unlock(deps: DepsMut, env: Env, caller: Caller, assets: Vec<Asset>, lock_id: u64) -> Result<Response, ContractError> {
+    assert_no_funds(&assets)?;
+    let owner = originator(deps.storage, &caller)?.ok_or(ContractError::NoOriginator {})?;
+    let mut lock = LOCKS.load(deps.storage, lock_id)?;
+    if lock.owner != owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if lock.unlocks_at.is_some() {
+        return Err(ContractError::LockUnlocking { id: lock_id });
+    }
+    let unlocks_at = env.block.time.plus_seconds(lock.duration);
+    lock.unlocks_at = Some(unlocks_at);
+    LOCKS.save(deps.storage, lock_id, &lock)?;
+    expect_incoming(deps.storage, unlocks_at, &lock.source(), &lock.coins)?;
+
+    let sender = env.contract.address.to_string();
+    let msgs = match lock.validator {
+        None => vec![stargate_msg(
+            BEGIN_UNLOCKING_TYPE_URL,
+            &MsgBeginUnlocking {
+                owner: sender,
+                id: lock_id,
+                coins: vec![],
+            },
+        )],
+        Some(_) => vec![
+            stargate_msg(
+                SUPERFLUID_UNDELEGATE_TYPE_URL,
+                &MsgSuperfluidUndelegate {
+                    sender: sender.clone(),
+                    lock_id,
+                },
+            ),
+            stargate_msg(SUPERFLUID_UNBOND_LOCK_TYPE_URL, &MsgSuperfluidUnbondLock { sender, lock_id }),
+        ],
+    };
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_unlock")
+        .add_event(
+            command_event(CommandKind::Unlock)
+                .add_attribute("lock_id", lock_id.to_string())
+                .add_attribute("unlocks_at", unlocks_at.to_string()),
+        )
+        .add_messages(msgs))
+}
+
+pub fn claim_unlocked(deps: DepsMut, env: Env, info: MessageInfo, lock_id: u64) -> Result<Response, ContractError> {
+    let lock = LOCKS.load(deps.storage, lock_id)?;
+    if lock.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    // the coins must be back and reserved for the owner, not paid out of what the
+    // contract holds for others
+    let coins = match lock.unlocks_at {
+        Some(unlocks_at) if unlocks_at <= env.block.time => take_incoming(deps.storage, unlocks_at, &lock.source())?,
+        _ => None,
+    }
+    .ok_or(ContractError::LockNotUnlocked { id: lock_id })?;
+    LOCKS.remove(deps.storage, lock_id);
+
+    let paid: Vec<Asset> = coins.iter().cloned().map(Asset::from).collect();
+    let mut response = Response::new()
+        .add_attribute("method", "claim_unlocked")
+        .add_attribute("lock_id", lock_id.to_string())
+        .add_event(
+            event("claim_unlocked")
+                .add_attribute("lock_id", lock_id.to_string())
+                .add_attribute("owner", &lock.owner)
+                .add_attributes(asset_attributes("", &paid)),
+        );
+    if !coins.is_empty() {
+        response = response.add_message(BankMsg::Send {
+            to_address: lock.owner.into(),
+            amount: coins,
+        });
+    }
+    Ok(response)
+}
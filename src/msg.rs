@@ -1,25 +1,153 @@
+use cosmwasm_std::{Binary, Coin, Decimal, Uint128, VoteOption};
+use cw20::{Cw20ReceiveMsg, Expiration};
+
+use crate::asset::Asset;
+use crate::asset::AssetInfo;
+use crate::state::{CommandFee, Dca, DcaDestination, Fee, LimitOrder, PipelineRecord, Position, ReferralMode, ScheduledPipeline, SwapRoute, Trigger};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
+    /// Defaults to the instantiator
+    pub admin: Option<String>,
+    /// Seconds, defaults to one hour
+    pub ibc_transfer_timeout: Option<u64>,
+    /// Seconds, defaults to five minutes
+    pub ibc_hop_timeout: Option<u64>,
+    pub fee: Option<Fee>,
+    #[serde(default)]
+    pub command_fees: Vec<CommandFee>,
+    #[serde(default)]
+    pub allowed_targets: Vec<String>,
+    #[serde(default)]
+    pub allowed_channels: Vec<String>,
+    #[serde(default)]
+    pub allowed_pools: Vec<u64>,
+    #[serde(default)]
+    pub allowed_contracts: Vec<String>,
+    #[serde(default)]
+    pub allowed_type_urls: Vec<String>,
+    /// cw20-ics20 contract used to bridge CW20 tokens over IBC
+    pub cw20_ics20_contract: Option<String>,
+    /// Highest share a referrer may ask for, referrals are disabled by default
+    #[serde(default)]
+    pub max_referral_bps: u16,
+    #[serde(default)]
+    pub referral_mode: ReferralMode,
+    /// Defaults to three nested hops
+    pub max_depth: Option<u32>,
+    /// Defaults to 16 commands
+    pub max_commands: Option<u32>,
+    /// Bytes, defaults to 16 KiB
+    pub max_packet_size: Option<u64>,
+    /// Keepers run scheduled pipelines for free by default
+    pub keeper_tip: Option<Fee>,
+    /// Seconds, defaults to 21 days
+    pub unbonding_period: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
+    /// Commands taking an `asset` spend exactly that asset, which has to match what was
+    /// received with the message. Without it they spend everything that was received.
     Transfer {
         address: String,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
     },
     IbcTransfer {
         channel_id: String,
         address: String,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
     },
     Swap {
         pool_id: u64,
         token_out_denom: String,
-        token_out_min_amount: String,
+        /// Decimal string, e.g. `"1000"`, same as before the amount was typed
+        token_out_min_amount: Uint128,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
+    },
+    /// Provides liquidity to an Osmosis pool. A single coin joins through
+    /// `MsgJoinSwapExternAmountIn`, several coins join balanced through `MsgJoinPool` and
+    /// what the pool does not take stays with the pipeline.
+    JoinPool {
+        pool_id: u64,
+        /// Shares to get for a balanced join, least shares to get for a single coin
+        share_out_amount: Uint128,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
+    },
+    /// Returns the `gamm/pool/{pool_id}` shares received to the pool for its coins
+    ExitPool {
+        pool_id: u64,
+        /// Least amount of each coin to get back
+        #[serde(default)]
+        token_out_mins: Vec<Coin>,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
+    },
+    /// Locks the coins received through Osmosis lockup for `duration` seconds, the lock
+    /// belongs to whoever started the pipeline
+    LockTokens {
+        duration: u64,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
     },
+    /// Locks the pool shares received and delegates them superfluid to `validator`, the
+    /// lock belongs to whoever started the pipeline
+    SuperfluidDelegate {
+        validator: String,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
+    },
+    /// Starts unlocking a lock of whoever started the pipeline, spends no funds. The coins
+    /// are claimed with `ClaimUnlocked` once the lock duration passed.
+    Unlock { lock_id: u64 },
+    /// Delegates the staking coin received to `validator` on behalf of whoever started the
+    /// pipeline
+    Delegate {
+        validator: String,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
+    },
+    /// Undelegates `amount` of the stake of whoever started the pipeline, spends no funds.
+    /// The coins are claimed with `ClaimUnbonded` once the unbonding period passed.
+    Undelegate { validator: String, amount: Uint128 },
+    /// Moves `amount` of the stake of whoever started the pipeline, spends no funds
+    Redelegate {
+        src_validator: String,
+        dst_validator: String,
+        amount: Uint128,
+    },
+    /// Hands the staking rewards of whoever started the pipeline to the pipeline, spends no funds
+    ClaimRewards { validator: String },
+    /// Votes on a proposal with the stake of whoever started the pipeline, spends no funds.
+    /// The contract casts one vote combining the votes of all its stakers by their stake.
+    Vote { proposal_id: u64, option: VoteOption },
+    /// `Vote` split over several options, the weights add up to one
+    VoteWeighted {
+        proposal_id: u64,
+        options: Vec<WeightedVoteOption>,
+    },
+    /// Calls an allowlisted contract with `msg`, attaching the pipeline funds `funds`
+    /// selects, or the funds an account sent along when they match it. Native coins it pays
+    /// back, and CW20 tokens the pipeline already owns, join the pipeline funds.
+    WasmExecute {
+        contract_addr: String,
+        msg: Binary,
+        funds: AmountSpec,
+        referrer: Option<Referrer>,
+    },
+    /// Pipeline only, sends a protobuf message of an allowlisted type, e.g.
+    /// `/osmosis.gamm.v1beta1.MsgSwapExactAmountIn`, with the contract as its signer. It is
+    /// handed no funds, taking more out of the contract balance than the pipeline owns fails
+    /// the step. Nothing checks what it does to delegations or locks, types acting on them
+    /// must stay off the allowlist.
+    StargateMsg { type_url: String, value: Binary },
     // todo: Purchase via nft mint just for HackAtom explanation,
     //  need to change it to real NFT purchase on market
     PurchaseNFT {
@@ -27,21 +155,558 @@ pub enum ExecuteMsg {
         contract_addr: String,
         token_id: String,
         token_uri: String,
+        asset: Option<Asset>,
+        referrer: Option<Referrer>,
     },
     ContractHop {
         contract_addr: String,
         commands: Vec<ExecuteMsg>,
+        referrer: Option<Referrer>,
     },
     IbcContractHop {
         channel: String,
-        commands: Vec<ExecuteMsg>
+        commands: Vec<ExecuteMsg>,
+        /// Funds the counterparty pipeline may spend out of the remote account of whoever
+        /// runs this command there, see `DepositRemote`
+        #[serde(default)]
+        remote_funds: Vec<Coin>,
+        referrer: Option<Referrer>,
+    },
+    /// Pipeline only, runs `then` when `condition` holds and `else` otherwise, in place
+    /// of this command
+    If {
+        condition: Condition,
+        #[serde(default)]
+        then: Vec<ExecuteMsg>,
+        #[serde(default, rename = "else")]
+        otherwise: Vec<ExecuteMsg>,
+    },
+    /// CW20 entry point, `msg` carries the command to run with the received tokens
+    Receive(Cw20ReceiveMsg),
+    /// Admin only, fields left empty are kept as they are
+    UpdateConfig {
+        ibc_transfer_timeout: Option<u64>,
+        ibc_hop_timeout: Option<u64>,
+        fee: Option<Fee>,
+        /// Replaces all the per-command fees
+        command_fees: Option<Vec<CommandFee>>,
+        allowed_targets: Option<Vec<String>>,
+        cw20_ics20_contract: Option<String>,
+        max_referral_bps: Option<u16>,
+        referral_mode: Option<ReferralMode>,
+        max_depth: Option<u32>,
+        max_commands: Option<u32>,
+        max_packet_size: Option<u64>,
+        keeper_tip: Option<Fee>,
+        unbonding_period: Option<u64>,
+        /// Settings to unset, they may not be set in the same update
+        #[serde(default)]
+        clear: Vec<OptionalSetting>,
+    },
+    /// Admin only, adds entries to and removes entries from the allowlists
+    UpdateAllowlist {
+        #[serde(default)]
+        add_targets: Vec<String>,
+        #[serde(default)]
+        remove_targets: Vec<String>,
+        #[serde(default)]
+        add_channels: Vec<String>,
+        #[serde(default)]
+        remove_channels: Vec<String>,
+        #[serde(default)]
+        add_pools: Vec<u64>,
+        #[serde(default)]
+        remove_pools: Vec<u64>,
+        #[serde(default)]
+        add_contracts: Vec<String>,
+        #[serde(default)]
+        remove_contracts: Vec<String>,
+        #[serde(default)]
+        add_type_urls: Vec<String>,
+        #[serde(default)]
+        remove_type_urls: Vec<String>,
+    },
+    /// Admin only, offers the admin role to `owner`
+    ProposeNewOwner {
+        owner: String,
+    },
+    /// Called by the proposed owner to become the admin
+    AcceptOwnership {},
+    /// Admin only, pauses or resumes capabilities, fields left empty are kept as they are
+    SetPause {
+        transfers: Option<bool>,
+        swaps: Option<bool>,
+        nft: Option<bool>,
+        hops: Option<bool>,
+        ibc_receive: Option<bool>,
+        staking: Option<bool>,
+        governance: Option<bool>,
+    },
+    /// Admin only, sends the accrued protocol fees of one asset, or all of them,
+    /// to `recipient` (the admin by default)
+    WithdrawFees {
+        asset_info: Option<AssetInfo>,
+        recipient: Option<String>,
+    },
+    /// Sends the referral fees credited to the sender
+    ClaimReferralFees {},
+    /// Escrows the funds sent along and runs `commands` with them once `trigger` is met
+    SchedulePipeline {
+        commands: Vec<ExecuteMsg>,
+        trigger: Trigger,
+    },
+    /// Runs a scheduled pipeline whose trigger is met. Anyone may call it and gets the
+    /// keeper tip.
+    ExecuteScheduled { id: u64 },
+    /// Owner only, refunds the escrow of a scheduled pipeline
+    CancelScheduled { id: u64 },
+    /// Escrows the native coin sent along until it can be swapped for at least
+    /// `min_price` of `token_out_denom` per unit
+    PlaceLimitOrder {
+        pool_id: u64,
+        token_out_denom: String,
+        min_price: Decimal,
+        expiry: Expiration,
+    },
+    /// Swaps a limit order whose price is reached and pays the output to the owner.
+    /// Anyone may call it and gets the keeper tip.
+    FillLimitOrder { id: u64 },
+    /// Refunds a limit order, the owner may cancel it at any time and anyone once it expired
+    CancelLimitOrder { id: u64 },
+    /// Escrows the native coin sent along, `amount_per_interval` times `occurrences`, and
+    /// swaps `amount_per_interval` of it along `pool_routes` every `interval_seconds`
+    CreateDca {
+        pool_routes: Vec<SwapRoute>,
+        amount_per_interval: Uint128,
+        interval_seconds: u64,
+        occurrences: u32,
+        /// Gets the output of every swap, the owner by default
+        destination: Option<DcaDestination>,
+        /// Least output of the last route each interval buys per coin of
+        /// `amount_per_interval`, fees and keeper tip included. Any price goes without one.
+        min_price: Option<Decimal>,
+    },
+    /// Runs the swap of a DCA that is due. Anyone may call it and gets the keeper tip.
+    ExecuteDca { id: u64 },
+    /// Owner only, stops a DCA and refunds what is left of its escrow
+    CancelDca { id: u64 },
+    /// Owner only, sends the coins of a lock that finished unlocking
+    ClaimUnlocked { lock_id: u64 },
+    /// Owner only, sends undelegated coins once they finished unbonding, less any slashing
+    ClaimUnbonded { id: u64 },
+    /// Owner only, mints a token factory token standing for a scheduled pipeline, DCA or
+    /// limit order. While it circulates the position cannot be cancelled and what its runs
+    /// leave is kept for the receipt. Positions paying a fixed recipient, like a DCA with a
+    /// destination, cannot have one.
+    MintReceipt { position: Position },
+    /// Burns the receipt sent along, pays what runs left for it to the sender, who becomes
+    /// the owner of the position unless it was settled already
+    RedeemReceipt {},
+    /// Credits the coins sent along to the remote account of `sender` on the other end of
+    /// `channel`. Its `IbcContractHop` packets spend from it and get back what they leave,
+    /// its `WithdrawRemote` packets pay it out.
+    DepositRemote { channel: String, sender: String },
+    /// Asks the counterparty on the other end of `channel` to pay `funds` out of the remote
+    /// account of the sender to `recipient`, e.g. back to whoever deposited them
+    WithdrawRemote {
+        channel: String,
+        funds: Vec<Coin>,
+        recipient: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// Required when migrating from 0.1.0, which did not store an admin
+    pub admin: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Returns `Config`
+    Config {},
+    /// Returns the proposed owner, if any, as `Option<Addr>`
+    PendingOwner {},
+    /// Returns `PauseFlags`
+    PauseFlags {},
+    /// Returns the protocol fees kept by the contract as `Vec<Asset>`
+    AccruedFees {},
+    /// Returns the referral fees `address` can claim as `Vec<Asset>`
+    ReferralBalance { address: String },
+    /// Returns the remote account of `sender` on `channel` as `Vec<Asset>`
+    RemoteBalance { channel: String, sender: String },
+    /// Walks `commands` as a pipeline owning `funds` would, without touching state.
+    /// Returns `SimulationResponse`
+    SimulatePipeline {
+        commands: Vec<ExecuteMsg>,
+        #[serde(default)]
+        funds: Vec<Asset>,
+    },
+    /// Returns `PipelineRecord`
+    Pipeline { id: u64 },
+    /// Returns the pipelines `sender` started, oldest first, as `PipelinesResponse`
+    PipelinesBySender {
+        sender: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns `ScheduledPipeline`
+    Scheduled { id: u64 },
+    /// Returns the pipelines waiting for their trigger, oldest first, as
+    /// `ScheduledPipelinesResponse`
+    ScheduledPipelines {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns `Dca`
+    Dca { id: u64 },
+    /// Returns the running DCAs, oldest first, as `DcasResponse`
+    Dcas {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns `LimitOrder`
+    LimitOrder { id: u64 },
+    /// Returns the lock the contract holds for an account as `Lock`
+    Lock { id: u64 },
+    /// Returns the stake the contract delegated for `owner`, by validator, as `DelegationsResponse`
+    Delegations {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns `Unbonding`
+    Unbonding { id: u64 },
+    /// Returns the `Receipt` minted for a position
+    Receipt { position: Position },
+    /// Returns the open limit orders, oldest first, as `LimitOrdersResponse`
+    LimitOrders {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DelegationsResponse {
+    pub delegations: Vec<DelegationResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DelegationResponse {
+    pub validator: String,
+    pub amount: Uint128,
+    /// Rewards settled by the last staking command on the validator, not claimed yet
+    pub pending_rewards: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DcasResponse {
+    pub dcas: Vec<Dca>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LimitOrdersResponse {
+    pub orders: Vec<LimitOrder>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ScheduledPipelinesResponse {
+    pub pipelines: Vec<ScheduledPipeline>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PipelinesResponse {
+    pub pipelines: Vec<PipelineRecord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    /// Steps simulated until the end of the pipeline or the first failed check
+    pub steps: Vec<StepSimulation>,
+    /// Funds the pipeline is expected to own after the last simulated step
+    pub funds_left: Vec<Asset>,
+    /// First check that failed, the steps after it were not simulated
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StepSimulation {
+    pub command: CommandKind,
+    /// Assets the step receives, fees included
+    pub input: Vec<Asset>,
+    pub fee: Vec<Asset>,
+    pub referral_fee: Vec<Asset>,
+    /// Assets the step is expected to give back to the pipeline
+    pub output: Vec<Asset>,
+    /// Steps of a `ContractHop` into this contract
+    pub steps: Vec<StepSimulation>,
+}
+
+/// What an `If` command checks when the pipeline reaches it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// The pipeline owns at least this asset
+    BalanceAtLeast(Asset),
+    /// The pipeline owns less than this asset
+    BalanceBelow(Asset),
+    /// A unit of `token_in_denom` buys more than `price` of `token_out_denom` at the
+    /// spot price of the pool
+    PriceAbove {
+        pool_id: u64,
+        token_in_denom: String,
+        token_out_denom: String,
+        price: Decimal,
+    },
+    /// A unit of `token_in_denom` buys less than `price` of `token_out_denom` at the
+    /// spot price of the pool
+    PriceBelow {
+        pool_id: u64,
+        token_in_denom: String,
+        token_out_denom: String,
+        price: Decimal,
     },
+    /// The step before the `If` succeeded, or there was none
+    PreviousStepSucceeded {},
+    /// The step before the `If` failed. Its changes are undone and the pipeline goes on
+    /// instead of failing as a whole.
+    PreviousStepFailed {},
+}
+
+impl Condition {
+    /// Whether the step before the `If` may fail without failing the pipeline
+    pub fn checks_previous_step(&self) -> bool {
+        matches!(self, Condition::PreviousStepSucceeded {} | Condition::PreviousStepFailed {})
+    }
+}
+
+/// Wallet or dApp that brought the command, credited with a share of its input
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Referrer {
+    pub address: String,
+    /// Basis points, capped by `Config::max_referral_bps`
+    pub bps: u16,
+}
+
+/// Funds a `WasmExecute` attaches to its call, out of the pipeline funds or out of what
+/// the account running it sent along
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountSpec {
+    None {},
+    All {},
+    /// These assets, the pipeline has to own them or the account has to send exactly them
+    Exact(Vec<Asset>),
+    /// Share of every asset the pipeline owns, above zero and at most one. An account has
+    /// to send exactly what it selects, so only a share of one.
+    Share(Decimal),
+}
+
+impl AmountSpec {
+    /// What is attached out of the pipeline `funds`
+    pub fn select(&self, funds: &[Asset]) -> Vec<Asset> {
+        match self {
+            AmountSpec::None {} => vec![],
+            AmountSpec::All {} => funds.to_vec(),
+            AmountSpec::Exact(assets) => assets.clone(),
+            AmountSpec::Share(share) => funds
+                .iter()
+                .map(|asset| Asset {
+                    info: asset.info.clone(),
+                    amount: asset.amount * *share,
+                })
+                .filter(|asset| !asset.amount.is_zero())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WeightedVoteOption {
+    pub option: VoteOption,
+    pub weight: Decimal,
+}
+
+/// Settings of the configuration `UpdateConfig` can unset
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionalSetting {
+    Fee,
+    KeeperTip,
+    Cw20Ics20Contract,
+}
+
+/// Command types that can have their own protocol fee
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandKind {
+    Transfer,
+    IbcTransfer,
+    Swap,
+    JoinPool,
+    ExitPool,
+    LockTokens,
+    SuperfluidDelegate,
+    Unlock,
+    Delegate,
+    Undelegate,
+    Redelegate,
+    ClaimRewards,
+    Vote,
+    VoteWeighted,
+    WasmExecute,
+    StargateMsg,
+    PurchaseNft,
+    ContractHop,
+    IbcContractHop,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+impl CommandKind {
+    /// Snake case name, as in JSON and event types
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandKind::Transfer => "transfer",
+            CommandKind::IbcTransfer => "ibc_transfer",
+            CommandKind::Swap => "swap",
+            CommandKind::JoinPool => "join_pool",
+            CommandKind::ExitPool => "exit_pool",
+            CommandKind::LockTokens => "lock_tokens",
+            CommandKind::SuperfluidDelegate => "superfluid_delegate",
+            CommandKind::Unlock => "unlock",
+            CommandKind::Delegate => "delegate",
+            CommandKind::Undelegate => "undelegate",
+            CommandKind::Redelegate => "redelegate",
+            CommandKind::ClaimRewards => "claim_rewards",
+            CommandKind::Vote => "vote",
+            CommandKind::VoteWeighted => "vote_weighted",
+            CommandKind::WasmExecute => "wasm_execute",
+            CommandKind::StargateMsg => "stargate_msg",
+            CommandKind::PurchaseNft => "purchase_nft",
+            CommandKind::ContractHop => "contract_hop",
+            CommandKind::IbcContractHop => "ibc_contract_hop",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum IbcExecuteMsg {
     IbcContractHop {
-        commands: Vec<ExecuteMsg>
+        commands: Vec<ExecuteMsg>,
+        /// Funds the pipeline spends out of the remote account of `sender`
+        #[serde(default)]
+        funds: Vec<Coin>,
+        /// Account on the sending chain the pipeline runs for, packets without one
+        /// cannot spend anything
+        #[serde(default)]
+        sender: Option<String>,
+        /// Pipeline step that sent the packet, used to record its acknowledgement
+        #[serde(default)]
+        origin: Option<PacketOrigin>,
     },
+    /// Pays `funds` out of the remote account of `sender` to `recipient`
+    WithdrawRemote {
+        funds: Vec<Coin>,
+        sender: String,
+        recipient: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PacketOrigin {
+    pub pipeline_id: u64,
+    pub step: u32,
+}
+
+impl ExecuteMsg {
+    /// Referrer of the command, if any
+    pub fn referrer(&self) -> Option<&Referrer> {
+        match self {
+            ExecuteMsg::Transfer { referrer, .. }
+            | ExecuteMsg::IbcTransfer { referrer, .. }
+            | ExecuteMsg::Swap { referrer, .. }
+            | ExecuteMsg::JoinPool { referrer, .. }
+            | ExecuteMsg::ExitPool { referrer, .. }
+            | ExecuteMsg::LockTokens { referrer, .. }
+            | ExecuteMsg::SuperfluidDelegate { referrer, .. }
+            | ExecuteMsg::Delegate { referrer, .. }
+            | ExecuteMsg::WasmExecute { referrer, .. }
+            | ExecuteMsg::PurchaseNFT { referrer, .. }
+            | ExecuteMsg::ContractHop { referrer, .. }
+            | ExecuteMsg::IbcContractHop { referrer, .. } => referrer.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Command type, none for messages that are not pipeline commands
+    pub fn kind(&self) -> Option<CommandKind> {
+        Some(match self {
+            ExecuteMsg::Transfer { .. } => CommandKind::Transfer,
+            ExecuteMsg::IbcTransfer { .. } => CommandKind::IbcTransfer,
+            ExecuteMsg::Swap { .. } => CommandKind::Swap,
+            ExecuteMsg::JoinPool { .. } => CommandKind::JoinPool,
+            ExecuteMsg::ExitPool { .. } => CommandKind::ExitPool,
+            ExecuteMsg::LockTokens { .. } => CommandKind::LockTokens,
+            ExecuteMsg::SuperfluidDelegate { .. } => CommandKind::SuperfluidDelegate,
+            ExecuteMsg::Unlock { .. } => CommandKind::Unlock,
+            ExecuteMsg::Delegate { .. } => CommandKind::Delegate,
+            ExecuteMsg::Undelegate { .. } => CommandKind::Undelegate,
+            ExecuteMsg::Redelegate { .. } => CommandKind::Redelegate,
+            ExecuteMsg::ClaimRewards { .. } => CommandKind::ClaimRewards,
+            ExecuteMsg::Vote { .. } => CommandKind::Vote,
+            ExecuteMsg::VoteWeighted { .. } => CommandKind::VoteWeighted,
+            ExecuteMsg::WasmExecute { .. } => CommandKind::WasmExecute,
+            ExecuteMsg::StargateMsg { .. } => CommandKind::StargateMsg,
+            ExecuteMsg::PurchaseNFT { .. } => CommandKind::PurchaseNft,
+            ExecuteMsg::ContractHop { .. } => CommandKind::ContractHop,
+            ExecuteMsg::IbcContractHop { .. } => CommandKind::IbcContractHop,
+            _ => return None,
+        })
+    }
+
+    /// Asset the command spends, if it was given explicitly
+    pub fn asset(&self) -> Option<&Asset> {
+        match self {
+            ExecuteMsg::Transfer { asset, .. }
+            | ExecuteMsg::IbcTransfer { asset, .. }
+            | ExecuteMsg::Swap { asset, .. }
+            | ExecuteMsg::JoinPool { asset, .. }
+            | ExecuteMsg::ExitPool { asset, .. }
+            | ExecuteMsg::LockTokens { asset, .. }
+            | ExecuteMsg::SuperfluidDelegate { asset, .. }
+            | ExecuteMsg::Delegate { asset, .. }
+            | ExecuteMsg::PurchaseNFT { asset, .. } => asset.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// What a pipeline step running the command is handed out of the pipeline `funds`:
+    /// the explicit asset, what `WasmExecute` selects, or everything
+    pub fn input(&self, funds: &[Asset]) -> Vec<Asset> {
+        if let ExecuteMsg::WasmExecute { funds: spec, .. } = self {
+            return spec.select(funds);
+        }
+        match self.asset() {
+            Some(asset) => vec![asset.clone()],
+            None if !self.spends_funds() => vec![],
+            None => funds.to_vec(),
+        }
+    }
+
+    /// Whether the command is handed funds, commands that only refer to something the
+    /// contract holds are not
+    pub fn spends_funds(&self) -> bool {
+        !matches!(
+            self,
+            ExecuteMsg::Unlock { .. }
+                | ExecuteMsg::Undelegate { .. }
+                | ExecuteMsg::Redelegate { .. }
+                | ExecuteMsg::ClaimRewards { .. }
+                | ExecuteMsg::Vote { .. }
+                | ExecuteMsg::VoteWeighted { .. }
+                | ExecuteMsg::StargateMsg { .. }
+                | ExecuteMsg::IbcContractHop { .. }
+        )
+    }
 }
@@ -0,0 +1,75 @@
+use std::str::FromStr;
+
+use cosmwasm_std::{Addr, Coin, CosmosMsg, Decimal, Deps, StdError, StdResult, Uint128};
+use cw_osmo_proto::cosmos::base::v1beta1::Coin as ProtoCoin;
+use cw_osmo_proto::osmosis::gamm::v1beta1::{
+    QuerySpotPriceRequest, QuerySpotPriceResponse, QuerySwapExactAmountInRequest,
+    QuerySwapExactAmountInResponse, SwapAmountInRoute,
+};
+use cw_osmo_proto::query::query_proto;
+
+pub const JOIN_POOL_TYPE_URL: &str = "/osmosis.gamm.v1beta1.MsgJoinPool";
+pub const EXIT_POOL_TYPE_URL: &str = "/osmosis.gamm.v1beta1.MsgExitPool";
+
+/// Denom of the liquidity shares of a pool
+pub fn share_denom(pool_id: u64) -> String {
+    format!("gamm/pool/{}", pool_id)
+}
+
+pub fn proto_coin(coin: &Coin) -> ProtoCoin {
+    ProtoCoin {
+        denom: coin.denom.clone(),
+        amount: coin.amount.to_string(),
+    }
+}
+
+/// Stargate message for the messages `cw-osmo-proto` has no type URL for
+pub fn stargate_msg<M: prost::Message>(type_url: &str, msg: &M) -> CosmosMsg {
+    CosmosMsg::Stargate {
+        type_url: type_url.to_string(),
+        value: msg.encode_to_vec().into(),
+    }
+}
+
+/// Amount of `token_out_denom` a unit of `token_in_denom` buys at the spot price of the pool
+pub fn spot_price(deps: Deps, pool_id: u64, token_in_denom: &str, token_out_denom: &str) -> StdResult<Decimal> {
+    // Osmosis reports how much of its `token_in_denom` pays for a unit of its
+    // `token_out_denom`, so the pair is asked for the other way round
+    let response: QuerySpotPriceResponse = query_proto(
+        deps,
+        QuerySpotPriceRequest {
+            pool_id,
+            token_in_denom: token_out_denom.to_string(),
+            token_out_denom: token_in_denom.to_string(),
+            with_swap_fee: false,
+        },
+    )?;
+    // the price may come with a denom suffix, e.g. `10.203uatom`
+    let spot_price = &response.spot_price;
+    let end = spot_price
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(spot_price.len());
+    Decimal::from_str(&spot_price[..end])
+        .map_err(|_| StdError::generic_err(format!("invalid spot price {}", spot_price)))
+}
+
+/// Amount of `token_out_denom` swapping `token_in` in the pool would return to `sender`
+pub fn estimate_swap(deps: Deps, sender: &Addr, token_in: &Coin, pool_id: u64, token_out_denom: &str) -> StdResult<Uint128> {
+    let response: QuerySwapExactAmountInResponse = query_proto(
+        deps,
+        QuerySwapExactAmountInRequest {
+            sender: sender.to_string(),
+            pool_id,
+            token_in: token_in.to_string(),
+            routes: vec![SwapAmountInRoute {
+                pool_id,
+                token_out_denom: token_out_denom.to_string(),
+            }],
+        },
+    )?;
+    let amount = response
+        .token_out_amount
+        .parse::<u128>()
+        .map_err(|_| StdError::generic_err("cannot parse the estimated swap output"))?;
+    Ok(Uint128::new(amount))
+}
@@ -0,0 +1,371 @@
+use cosmwasm_std::{to_binary, Addr, CosmosMsg, Deps, DepsMut, Env, Event, Order, Response, StdError, StdResult, Storage, SubMsg, SubMsgResult, Uint128};
+use cw20::{BalanceResponse, Cw20QueryMsg};
+
+use crate::asset::{deduct, execute_msg_with_assets, Asset, AssetInfo};
+use crate::events::{asset_attributes, event};
+use crate::condition::{evaluate, tolerates_failure};
+use crate::history::{fail_step, finish_pipeline, finish_step, record_pipeline, record_step};
+use crate::msg::{CommandKind, ExecuteMsg};
+use crate::receipt::credit_receipt;
+use crate::remote::credit_remote;
+use crate::state::{Beneficiary, Pipeline, PIPELINES, RESERVED};
+use crate::ContractError;
+
+pub const HOP_REPLY_ID: u64 = 1;
+
+/// Who a command runs for, which decides the funds it may spend
+#[derive(Clone, Debug, PartialEq)]
+pub enum Caller {
+    /// The contract itself running a pipeline step, spends what the pipeline forwarded
+    Pipeline,
+    /// Counterparty contract on an allowlisted channel running a pipeline for `sender`,
+    /// spends what its packet takes out of the remote account of `sender`
+    IbcCounterparty { channel: String, sender: String },
+    /// Any other account or contract, spends what it sent
+    External(Addr),
+}
+
+impl Caller {
+    /// Account unspent funds go back to, none when they stay with the contract
+    pub fn refund_address(&self) -> Option<Addr> {
+        match self {
+            Caller::External(addr) => Some(addr.clone()),
+            _ => None,
+        }
+    }
+
+    /// Who gets what a pipeline run for the caller leaves
+    pub fn beneficiary(&self) -> Beneficiary {
+        match self {
+            Caller::Pipeline => Beneficiary::Pipeline,
+            Caller::IbcCounterparty { channel, sender } => Beneficiary::Remote {
+                channel: channel.clone(),
+                sender: sender.clone(),
+            },
+            Caller::External(addr) => Beneficiary::Account(addr.clone()),
+        }
+    }
+}
+
+/// The contract only calls itself to run pipeline steps, any other self call is refused
+pub fn resolve_caller(storage: &dyn Storage, env: &Env, sender: Addr) -> Result<Caller, ContractError> {
+    if sender != env.contract.address {
+        return Ok(Caller::External(sender));
+    }
+    match PIPELINES.may_load(storage)? {
+        Some(pipelines) if !pipelines.is_empty() => Ok(Caller::Pipeline),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
+
+/// A CW20 `Receive` names its sender itself, it only runs as a pipeline step when the
+/// token is the one the running step was handed, anything else runs for the named sender
+pub fn resolve_cw20_caller(storage: &dyn Storage, env: &Env, received: &[Asset], sender: Addr) -> Result<Caller, ContractError> {
+    if sender != env.contract.address {
+        return Ok(Caller::External(sender));
+    }
+    let running = PIPELINES.may_load(storage)?.unwrap_or_default().pop();
+    match running {
+        Some(pipeline) if pipeline.step_input == received => Ok(Caller::Pipeline),
+        _ => Ok(Caller::External(sender)),
+    }
+}
+
+/// Starts running `commands` with `funds` for `caller`, returns the pipeline id with the
+/// messages running the first step, or no id with the refunds when there is nothing to run.
+/// `beneficiary` gets what the pipeline leaves. A pipeline whose first step cannot run fails
+/// before anything is written.
+pub fn start_pipeline(
+    mut deps: DepsMut,
+    env: &Env,
+    contract_addr: Addr,
+    commands: Vec<ExecuteMsg>,
+    funds: Vec<Asset>,
+    caller: &Caller,
+    beneficiary: Beneficiary,
+) -> Result<(Option<u64>, Vec<SubMsg>), ContractError> {
+    if commands.is_empty() {
+        return Ok((None, pay_out(deps.storage, &beneficiary, &funds)?));
+    }
+
+    // the first command runs now, the rest after each reply. A hop nested in this
+    // pipeline pushes its own entry and removes it before we get the reply.
+    let mut pipeline = new_pipeline(contract_addr, commands.clone(), funds.clone(), beneficiary);
+    let step = take_step(deps.as_ref(), &mut pipeline)?;
+    let id = record_pipeline(deps.storage, env, caller, &pipeline.contract_addr, &commands, &funds)?;
+    pipeline.id = id;
+    let msgs = match step {
+        Some(step) => {
+            let msg = begin_step(deps.branch(), env, &mut pipeline, step)?;
+            let mut pipelines = PIPELINES.may_load(deps.storage)?.unwrap_or_default();
+            pipelines.push(pipeline);
+            PIPELINES.save(deps.storage, &pipelines)?;
+            vec![msg]
+        }
+        // every `If` on the way chose an empty branch
+        None => finish(deps.storage, env, &pipeline)?.1,
+    };
+
+    Ok((Some(id), msgs))
+}
+
+/// Fails like `start_pipeline` would when the first step of `commands` cannot run with
+/// `funds`, without writing anything
+pub fn assert_can_start(deps: Deps, env: &Env, commands: Vec<ExecuteMsg>, funds: Vec<Asset>) -> Result<(), ContractError> {
+    let mut pipeline = new_pipeline(env.contract.address.clone(), commands, funds, Beneficiary::Pipeline);
+    take_step(deps, &mut pipeline)?;
+    Ok(())
+}
+
+fn new_pipeline(contract_addr: Addr, commands: Vec<ExecuteMsg>, funds: Vec<Asset>, beneficiary: Beneficiary) -> Pipeline {
+    Pipeline {
+        id: 0,
+        contract_addr,
+        commands,
+        funds,
+        balances_before: vec![],
+        reserved_before: vec![],
+        beneficiary,
+        previous_step_failed: false,
+        step_input: vec![],
+    }
+}
+
+/// A pipeline command ready to run: its kind, the funds it is handed and the call to the
+/// hop contract
+type Step = (CommandKind, Vec<Asset>, CosmosMsg);
+
+/// Takes the next pipeline command to run off `pipeline`, none when the pipeline is done.
+/// A command with an explicit asset only gets that asset, otherwise it gets all the
+/// pipeline funds, it fails when the pipeline does not own what the command asks for. `If`
+/// commands are replaced by the branch they choose on the way.
+fn take_step(deps: Deps, pipeline: &mut Pipeline) -> Result<Option<Step>, ContractError> {
+    loop {
+        if pipeline.commands.is_empty() {
+            return Ok(None);
+        }
+        let command = pipeline.commands.remove(0);
+        if let ExecuteMsg::If { condition, then, otherwise } = command {
+            let met = evaluate(deps, &condition, &pipeline.funds, pipeline.previous_step_failed)?;
+            let branch = if met { then } else { otherwise };
+            pipeline.commands.splice(0..0, branch);
+            continue;
+        }
+
+        let kind = command.kind().ok_or(ContractError::Unauthorized {})?;
+        let funds = command.input(&pipeline.funds);
+        // the contract holds funds for others too, a step only gets those of its pipeline
+        deduct(&pipeline.funds, &funds)?;
+        let msg = execute_msg_with_assets(pipeline.contract_addr.as_str(), to_binary(&command)?, &funds)?;
+        return Ok(Some((kind, funds, msg)));
+    }
+}
+
+/// Records `step` as the running step of `pipeline`, returns its call replying to the contract
+fn begin_step(deps: DepsMut, env: &Env, pipeline: &mut Pipeline, (kind, funds, msg): Step) -> Result<SubMsg, ContractError> {
+    pipeline.balances_before = tracked_balances(deps.as_ref(), env, &pipeline.funds)?;
+    pipeline.step_input = funds.clone();
+    record_step(deps.storage, env, pipeline.id, kind, funds)?;
+    pipeline.reserved_before = reserved(deps.storage)?;
+    pipeline.previous_step_failed = false;
+
+    // a step followed by a check of its outcome may fail, it is undone and the
+    // pipeline goes on
+    Ok(if tolerates_failure(pipeline.commands.first()) {
+        SubMsg::reply_always(msg, HOP_REPLY_ID)
+    } else {
+        SubMsg::reply_on_success(msg, HOP_REPLY_ID)
+    })
+}
+
+/// Wraps the next pipeline command into a call to the hop contract, none when the pipeline
+/// is done
+fn next_step(deps: DepsMut, env: &Env, pipeline: &mut Pipeline) -> Result<Option<SubMsg>, ContractError> {
+    match take_step(deps.as_ref(), pipeline)? {
+        Some(step) => Ok(Some(begin_step(deps, env, pipeline, step)?)),
+        None => Ok(None),
+    }
+}
+
+/// Completes the pipeline record, returns the completion event with the refunds
+fn finish(storage: &mut dyn Storage, env: &Env, pipeline: &Pipeline) -> Result<(Event, Vec<SubMsg>), ContractError> {
+    finish_pipeline(storage, env, pipeline.id)?;
+    let event = event("pipeline_completed")
+        .add_attribute("execution_id", pipeline.id.to_string())
+        .add_attributes(asset_attributes("funds_left_", &pipeline.funds));
+    Ok((event, pay_out(storage, &pipeline.beneficiary, &pipeline.funds)?))
+}
+
+pub fn hop_reply(mut deps: DepsMut, env: Env, msg: SubMsgResult) -> Result<Response, ContractError> {
+    let mut pipelines = PIPELINES.load(deps.storage)?;
+    let mut pipeline = pipelines
+        .pop()
+        .ok_or_else(|| StdError::generic_err("no pipeline is running"))?;
+
+    let mut events = vec![];
+    match msg.into_result() {
+        Ok(_) => {
+            // whatever the step spent or produced changes what the pipeline owns
+            let before = BalanceSnapshot {
+                held: &pipeline.balances_before,
+                reserved: &pipeline.reserved_before,
+            };
+            let held_after = tracked_balances(deps.as_ref(), &env, &pipeline.funds)?;
+            let reserved_after = reserved(deps.storage)?;
+            let after = BalanceSnapshot {
+                held: &held_after,
+                reserved: &reserved_after,
+            };
+            let (funds, output) = apply_balance_change(&pipeline.funds, &before, &after)?;
+            pipeline.funds = funds;
+            if let Some((step, command)) = finish_step(deps.storage, &env, pipeline.id, output.clone())? {
+                events.push(step_event(pipeline.id, step, command, "succeeded").add_attributes(asset_attributes("output_", &output)));
+            }
+        }
+        // only steps followed by a check of their outcome reply on failure
+        Err(err) => {
+            pipeline.previous_step_failed = true;
+            if let Some((step, command)) = fail_step(deps.storage, &env, pipeline.id, err.clone())? {
+                events.push(step_event(pipeline.id, step, command, "failed").add_attribute("error", err));
+            }
+        }
+    }
+
+    let msgs = match next_step(deps.branch(), &env, &mut pipeline)? {
+        Some(msg) => {
+            pipelines.push(pipeline);
+            vec![msg]
+        }
+        None => {
+            let (event, refunds) = finish(deps.storage, &env, &pipeline)?;
+            events.push(event);
+            refunds
+        }
+    };
+
+    PIPELINES.save(deps.storage, &pipelines)?;
+    Ok(Response::new()
+        .add_attribute("method", "hop_reply")
+        .add_events(events)
+        .add_submessages(msgs))
+}
+
+fn step_event(id: u64, step: u32, command: CommandKind, status: &str) -> Event {
+    event("hop_step")
+        .add_attribute("execution_id", id.to_string())
+        .add_attribute("step", step.to_string())
+        .add_attribute("command", command.as_str())
+        .add_attribute("status", status)
+}
+
+/// Hands the funds a pipeline leaves to its beneficiary
+fn pay_out(storage: &mut dyn Storage, beneficiary: &Beneficiary, funds: &[Asset]) -> Result<Vec<SubMsg>, ContractError> {
+    match beneficiary {
+        Beneficiary::Pipeline => Ok(vec![]),
+        Beneficiary::Account(addr) => funds
+            .iter()
+            .map(|asset| Ok(SubMsg::new(asset.transfer_msg(addr.as_str())?)))
+            .collect(),
+        Beneficiary::Remote { channel, sender } => {
+            credit_remote(storage, channel, sender, funds)?;
+            Ok(vec![])
+        }
+        Beneficiary::Receipt(subdenom) => {
+            credit_receipt(storage, subdenom, funds)?;
+            Ok(vec![])
+        }
+    }
+}
+
+/// Balances of the contract: every native coin plus the CW20 tokens the pipeline holds
+pub fn tracked_balances(deps: Deps, env: &Env, funds: &[Asset]) -> StdResult<Vec<Asset>> {
+    let mut balances: Vec<Asset> = deps
+        .querier
+        .query_all_balances(&env.contract.address)?
+        .into_iter()
+        .map(Asset::from)
+        .collect();
+    for asset in funds {
+        if let AssetInfo::Cw20(token) = &asset.info {
+            let response: BalanceResponse = deps.querier.query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: env.contract.address.to_string(),
+                },
+            )?;
+            balances.push(Asset {
+                info: asset.info.clone(),
+                amount: response.balance,
+            });
+        }
+    }
+    Ok(balances)
+}
+
+/// Everything `RESERVED` holds, by asset key
+pub fn reserved(storage: &dyn Storage) -> StdResult<Vec<(String, Uint128)>> {
+    RESERVED.range(storage, None, None, Order::Ascending).collect()
+}
+
+/// Contract balances and reservations on one side of a pipeline step
+pub struct BalanceSnapshot<'a> {
+    pub held: &'a [Asset],
+    pub reserved: &'a [(String, Uint128)],
+}
+
+impl BalanceSnapshot<'_> {
+    fn held(&self, info: &AssetInfo) -> Uint128 {
+        self.held
+            .iter()
+            .find(|asset| asset.info == *info)
+            .map(|asset| asset.amount)
+            .unwrap_or_default()
+    }
+
+    fn reserved(&self, info: &AssetInfo) -> Uint128 {
+        let key = info.to_string();
+        self.reserved
+            .iter()
+            .find(|(reserved, _)| *reserved == key)
+            .map(|(_, amount)| *amount)
+            .unwrap_or_default()
+    }
+}
+
+/// Pipeline funds after a step that moved the contract from `before` to `after`, with what
+/// the step produced. Funds the step reserved for someone are spent, funds it released are
+/// gained, a step that spent more than the pipeline owns fails instead of eating into what
+/// the contract holds for others.
+pub fn apply_balance_change(funds: &[Asset], before: &BalanceSnapshot, after: &BalanceSnapshot) -> Result<(Vec<Asset>, Vec<Asset>), ContractError> {
+    let mut infos: Vec<&AssetInfo> = vec![];
+    for asset in funds.iter().chain(before.held).chain(after.held) {
+        if !infos.contains(&&asset.info) {
+            infos.push(&asset.info);
+        }
+    }
+
+    let mut result = vec![];
+    let mut output = vec![];
+    for info in infos {
+        let owned = funds
+            .iter()
+            .find(|asset| asset.info == *info)
+            .map(|asset| asset.amount)
+            .unwrap_or_default();
+        let received = after.held(info) + before.reserved(info);
+        let spent = before.held(info) + after.reserved(info);
+        let amount = (owned + received)
+            .checked_sub(spent)
+            .map_err(|_| ContractError::StepOverspent { asset: info.to_string() })?;
+        if amount > Uint128::zero() {
+            result.push(Asset { info: info.clone(), amount });
+        }
+        if received > spent {
+            output.push(Asset {
+                info: info.clone(),
+                amount: received - spent,
+            });
+        }
+    }
+    Ok((result, output))
+}
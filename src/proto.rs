@@ -0,0 +1,128 @@
+//! Messages `cw-osmo-proto` does not ship, laid out as in the Osmosis and Cosmos SDK proto files
+
+use cw_osmo_proto::cosmos::base::v1beta1::Coin;
+
+pub const LOCK_TOKENS_TYPE_URL: &str = "/osmosis.lockup.MsgLockTokens";
+pub const BEGIN_UNLOCKING_TYPE_URL: &str = "/osmosis.lockup.MsgBeginUnlocking";
+pub const LOCK_AND_SUPERFLUID_DELEGATE_TYPE_URL: &str = "/osmosis.superfluid.MsgLockAndSuperfluidDelegate";
+pub const SUPERFLUID_UNDELEGATE_TYPE_URL: &str = "/osmosis.superfluid.MsgSuperfluidUndelegate";
+pub const SUPERFLUID_UNBOND_LOCK_TYPE_URL: &str = "/osmosis.superfluid.MsgSuperfluidUnbondLock";
+pub const VOTE_WEIGHTED_TYPE_URL: &str = "/cosmos.gov.v1beta1.MsgVoteWeighted";
+pub const CREATE_DENOM_TYPE_URL: &str = "/osmosis.tokenfactory.v1beta1.MsgCreateDenom";
+pub const MINT_TYPE_URL: &str = "/osmosis.tokenfactory.v1beta1.MsgMint";
+pub const BURN_TYPE_URL: &str = "/osmosis.tokenfactory.v1beta1.MsgBurn";
+
+/// `google.protobuf.Duration`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Duration {
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+    #[prost(int32, tag = "2")]
+    pub nanos: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgLockTokens {
+    #[prost(string, tag = "1")]
+    pub owner: String,
+    #[prost(message, optional, tag = "2")]
+    pub duration: Option<Duration>,
+    #[prost(message, repeated, tag = "3")]
+    pub coins: Vec<Coin>,
+}
+
+/// Also the layout of `MsgLockAndSuperfluidDelegateResponse`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgLockTokensResponse {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+/// Unlocks all the coins of the lock when `coins` is empty
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgBeginUnlocking {
+    #[prost(string, tag = "1")]
+    pub owner: String,
+    #[prost(uint64, tag = "2")]
+    pub id: u64,
+    #[prost(message, repeated, tag = "3")]
+    pub coins: Vec<Coin>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgLockAndSuperfluidDelegate {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(message, repeated, tag = "2")]
+    pub coins: Vec<Coin>,
+    #[prost(string, tag = "3")]
+    pub val_addr: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgSuperfluidUndelegate {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(uint64, tag = "2")]
+    pub lock_id: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgSuperfluidUnbondLock {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(uint64, tag = "2")]
+    pub lock_id: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgVoteWeighted {
+    #[prost(uint64, tag = "1")]
+    pub proposal_id: u64,
+    #[prost(string, tag = "2")]
+    pub voter: String,
+    #[prost(message, repeated, tag = "3")]
+    pub options: Vec<WeightedVoteOption>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WeightedVoteOption {
+    /// `VoteOption`: 1 yes, 2 abstain, 3 no, 4 no with veto
+    #[prost(int32, tag = "1")]
+    pub option: i32,
+    /// `sdk.Dec` in its wire form, the integer scaled by 10^18
+    #[prost(string, tag = "2")]
+    pub weight: String,
+}
+
+/// Creates `factory/{sender}/{subdenom}`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgCreateDenom {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(string, tag = "2")]
+    pub subdenom: String,
+}
+
+/// Mints to the sender when `mint_to_address` is empty, which chains without the field
+/// accept as well
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgMint {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(message, optional, tag = "2")]
+    pub amount: Option<Coin>,
+    #[prost(string, tag = "3")]
+    pub mint_to_address: String,
+}
+
+/// Burns from the sender when `burn_from_address` is empty
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgBurn {
+    #[prost(string, tag = "1")]
+    pub sender: String,
+    #[prost(message, optional, tag = "2")]
+    pub amount: Option<Coin>,
+    #[prost(string, tag = "3")]
+    pub burn_from_address: String,
+}
@@ -0,0 +1,217 @@
+use cosmwasm_std::{Addr, BankMsg, Coin, DepsMut, Env, MessageInfo, Response, StdResult, Storage};
+
+use crate::asset::{join_assets, Asset};
+use crate::events::{asset_attributes, event};
+use crate::fees::{release, reserve};
+use crate::msg::ExecuteMsg;
+use crate::osmosis::{proto_coin, stargate_msg};
+use crate::proto::{MsgBurn, MsgCreateDenom, MsgMint, BURN_TYPE_URL, CREATE_DENOM_TYPE_URL, MINT_TYPE_URL};
+use crate::state::{Beneficiary, Position, Receipt, DCAS, LIMIT_ORDERS, RECEIPTS, SCHEDULED};
+use crate::ContractError;
+
+fn position_owner(storage: &dyn Storage, position: &Position) -> StdResult<Addr> {
+    Ok(match position {
+        Position::Scheduled(id) => SCHEDULED.load(storage, *id)?.owner,
+        Position::Dca(id) => DCAS.load(storage, *id)?.owner,
+        Position::LimitOrder(id) => LIMIT_ORDERS.load(storage, *id)?.owner,
+    })
+}
+
+/// Hands the position to `owner`, returns false when it was settled already
+fn set_position_owner(storage: &mut dyn Storage, position: &Position, owner: Addr) -> StdResult<bool> {
+    match position {
+        Position::Scheduled(id) => match SCHEDULED.may_load(storage, *id)? {
+            Some(mut scheduled) => {
+                scheduled.owner = owner;
+                SCHEDULED.save(storage, *id, &scheduled)?;
+            }
+            None => return Ok(false),
+        },
+        Position::Dca(id) => match DCAS.may_load(storage, *id)? {
+            Some(mut dca) => {
+                dca.owner = owner;
+                DCAS.save(storage, *id, &dca)?;
+            }
+            None => return Ok(false),
+        },
+        Position::LimitOrder(id) => match LIMIT_ORDERS.may_load(storage, *id)? {
+            Some(mut order) => {
+                order.owner = owner;
+                LIMIT_ORDERS.save(storage, *id, &order)?;
+            }
+            None => return Ok(false),
+        },
+    }
+    Ok(true)
+}
+
+/// Who gets what a run of the position leaves: the receipt while it circulates, the
+/// owner otherwise
+pub fn position_beneficiary(storage: &dyn Storage, position: &Position, owner: &Addr) -> StdResult<Beneficiary> {
+    let subdenom = position.subdenom();
+    Ok(match RECEIPTS.may_load(storage, &subdenom)? {
+        Some(receipt) if receipt.outstanding => Beneficiary::Receipt(subdenom),
+        _ => Beneficiary::Account(owner.clone()),
+    })
+}
+
+/// Adds `assets` to the proceeds of the receipt and reserves them
+pub fn credit_receipt(storage: &mut dyn Storage, subdenom: &str, assets: &[Asset]) -> StdResult<()> {
+    let mut receipt = RECEIPTS.load(storage, subdenom)?;
+    for asset in assets {
+        match receipt.proceeds.iter_mut().find(|owned| owned.info == asset.info) {
+            Some(owned) => owned.amount += asset.amount,
+            None => receipt.proceeds.push(asset.clone()),
+        }
+        reserve(storage, asset)?;
+    }
+    RECEIPTS.save(storage, subdenom, &receipt)
+}
+
+/// Fails while a receipt of the position circulates, whoever holds it may own the position
+pub fn assert_no_receipt(storage: &dyn Storage, position: &Position) -> Result<(), ContractError> {
+    match RECEIPTS.may_load(storage, &position.subdenom())? {
+        Some(receipt) if receipt.outstanding => Err(ContractError::ReceiptOutstanding { denom: receipt.denom }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether any of `commands` pays out to an address of its own instead of leaving the
+/// funds to the beneficiary of the pipeline
+fn pays_fixed_recipient(commands: &[ExecuteMsg]) -> bool {
+    commands.iter().any(|command| match command {
+        ExecuteMsg::Transfer { .. } | ExecuteMsg::IbcTransfer { .. } => true,
+        ExecuteMsg::If { then, otherwise, .. } => pays_fixed_recipient(then) || pays_fixed_recipient(otherwise),
+        ExecuteMsg::ContractHop { commands, .. } => pays_fixed_recipient(commands),
+        _ => false,
+    })
+}
+
+/// Fails for positions whose runs pay a recipient fixed at creation, what they leave
+/// would not go to whoever holds the receipt. A limit order always leaves its output.
+fn assert_pays_beneficiary(storage: &dyn Storage, position: &Position) -> Result<(), ContractError> {
+    let fixed = match position {
+        Position::Scheduled(id) => pays_fixed_recipient(&SCHEDULED.load(storage, *id)?.commands),
+        Position::Dca(id) => DCAS.load(storage, *id)?.destination.is_some(),
+        Position::LimitOrder(_) => false,
+    };
+    if fixed {
+        return Err(ContractError::FixedRecipient { position: position.subdenom() });
+    }
+    Ok(())
+}
+
+/// Owner only, mints one unit of `factory/{contract}/{subdenom}` standing for the position
+/// and sends it to the owner. The denom is created the first time.
+pub fn mint_receipt(deps: DepsMut, env: Env, info: MessageInfo, position: Position) -> Result<Response, ContractError> {
+    if position_owner(deps.storage, &position)? != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    assert_no_receipt(deps.storage, &position)?;
+    assert_pays_beneficiary(deps.storage, &position)?;
+
+    let subdenom = position.subdenom();
+    let contract = env.contract.address.to_string();
+    let denom = format!("factory/{}/{}", contract, subdenom);
+    let mut msgs = vec![];
+    if !RECEIPTS.has(deps.storage, &subdenom) {
+        msgs.push(stargate_msg(
+            CREATE_DENOM_TYPE_URL,
+            &MsgCreateDenom {
+                sender: contract.clone(),
+                subdenom: subdenom.clone(),
+            },
+        ));
+    }
+    let token = Coin::new(1, &denom);
+    msgs.push(stargate_msg(
+        MINT_TYPE_URL,
+        &MsgMint {
+            sender: contract,
+            amount: Some(proto_coin(&token)),
+            mint_to_address: String::new(),
+        },
+    ));
+    msgs.push(
+        BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![token],
+        }
+        .into(),
+    );
+    RECEIPTS.save(
+        deps.storage,
+        &subdenom,
+        &Receipt {
+            position,
+            denom: denom.clone(),
+            outstanding: true,
+            proceeds: vec![],
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "mint_receipt")
+        .add_attribute("denom", &denom)
+        .add_event(
+            event("mint_receipt")
+                .add_attribute("denom", denom)
+                .add_attribute("owner", &info.sender),
+        )
+        .add_messages(msgs))
+}
+
+/// Burns the receipt sent along, pays its proceeds to the sender and makes it the owner of
+/// the position unless that was settled already
+pub fn redeem_receipt(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let token = match info.funds.as_slice() {
+        [token] if token.amount.u128() == 1 => token.clone(),
+        _ => {
+            let received: Vec<Asset> = info.funds.into_iter().map(Asset::from).collect();
+            return Err(ContractError::FundsMismatch {
+                expected: "one receipt token".to_string(),
+                received: join_assets(&received),
+            });
+        }
+    };
+    let prefix = format!("factory/{}/", env.contract.address);
+    let mut receipt = token
+        .denom
+        .strip_prefix(&prefix)
+        .map(|subdenom| RECEIPTS.may_load(deps.storage, subdenom))
+        .transpose()?
+        .flatten()
+        .filter(|receipt| receipt.outstanding)
+        .ok_or_else(|| ContractError::UnknownReceipt { denom: token.denom.clone() })?;
+
+    let live = set_position_owner(deps.storage, &receipt.position, info.sender.clone())?;
+    let proceeds = std::mem::take(&mut receipt.proceeds);
+    let mut msgs = vec![];
+    for asset in &proceeds {
+        release(deps.storage, asset)?;
+        msgs.push(asset.transfer_msg(info.sender.as_str())?);
+    }
+    receipt.outstanding = false;
+    RECEIPTS.save(deps.storage, &receipt.position.subdenom(), &receipt)?;
+
+    let burn = stargate_msg(
+        BURN_TYPE_URL,
+        &MsgBurn {
+            sender: env.contract.address.into(),
+            amount: Some(proto_coin(&token)),
+            burn_from_address: String::new(),
+        },
+    );
+    Ok(Response::new()
+        .add_attribute("method", "redeem_receipt")
+        .add_attribute("denom", &token.denom)
+        .add_event(
+            event("redeem_receipt")
+                .add_attribute("denom", &token.denom)
+                .add_attribute("owner", &info.sender)
+                .add_attribute("settled", (!live).to_string())
+                .add_attributes(asset_attributes("proceeds_", &proceeds)),
+        )
+        .add_message(burn)
+        .add_messages(msgs))
+}
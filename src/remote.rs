@@ -0,0 +1,147 @@
+use cosmwasm_std::{to_binary, Coin, DepsMut, Env, IbcMsg, IbcReceiveResponse, IbcTimeout, MessageInfo, Order, Response, StdError, StdResult, Storage, Uint128};
+
+use crate::ack::make_ack_success;
+use crate::asset::{assert_no_funds, deduct, join_assets, Asset};
+use crate::events::{asset_attributes, event};
+use crate::fees::{release, reserve};
+use crate::msg::IbcExecuteMsg;
+use crate::state::{CONFIG, REMOTE_BALANCES};
+use crate::validation::validate_packet_size;
+use crate::ContractError;
+
+/// Adds `assets` to the remote account of `sender` on `channel` and reserves them
+pub fn credit_remote(storage: &mut dyn Storage, channel: &str, sender: &str, assets: &[Asset]) -> StdResult<()> {
+    for asset in assets {
+        REMOTE_BALANCES.update(storage, (channel, sender, asset.info.to_string()), |balance| -> StdResult<_> {
+            let mut balance = balance.unwrap_or_else(|| Asset {
+                info: asset.info.clone(),
+                amount: Uint128::zero(),
+            });
+            balance.amount += asset.amount;
+            Ok(balance)
+        })?;
+        reserve(storage, asset)?;
+    }
+    Ok(())
+}
+
+/// Takes `assets` out of the remote account of `sender` on `channel`, fails when it does
+/// not hold them
+pub fn debit_remote(storage: &mut dyn Storage, channel: &str, sender: &str, assets: &[Asset]) -> Result<(), ContractError> {
+    for asset in assets {
+        let key = (channel, sender, asset.info.to_string());
+        let mut balance = REMOTE_BALANCES
+            .may_load(storage, key.clone())?
+            .filter(|balance| balance.amount >= asset.amount)
+            .ok_or_else(|| ContractError::InsufficientFunds { funds: asset.to_string() })?;
+        balance.amount -= asset.amount;
+        if balance.amount.is_zero() {
+            REMOTE_BALANCES.remove(storage, key);
+        } else {
+            REMOTE_BALANCES.save(storage, key, &balance)?;
+        }
+        release(storage, asset)?;
+    }
+    Ok(())
+}
+
+pub fn remote_balance(storage: &dyn Storage, channel: &str, sender: &str) -> StdResult<Vec<Asset>> {
+    REMOTE_BALANCES
+        .prefix((channel, sender))
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, asset)| asset))
+        .collect()
+}
+
+/// Credits the coins sent along to the remote account of `sender`, the account on the
+/// other end of `channel` whose packets may spend them
+pub fn deposit_remote(deps: DepsMut, info: MessageInfo, channel: String, sender: String) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if !config.allowed_channels.contains(&channel) {
+        return Err(ContractError::ChannelNotAllowed { channel });
+    }
+    let assets: Vec<Asset> = info.funds.into_iter().map(Asset::from).collect();
+    if assets.is_empty() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "you must send the coins you wish to use",
+        )));
+    }
+    credit_remote(deps.storage, &channel, &sender, &assets)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "deposit_remote")
+        .add_attribute("amount", join_assets(&assets))
+        .add_event(
+            event("deposit_remote")
+                .add_attribute("channel", channel)
+                .add_attribute("remote_sender", sender)
+                .add_attribute("depositor", info.sender)
+                .add_attributes(asset_attributes("", &assets)),
+        ))
+}
+
+/// Sends the packet asking the counterparty to pay `funds` out of the remote account of the
+/// sender to `recipient`
+pub fn withdraw_remote(deps: DepsMut, env: Env, info: MessageInfo, channel: String, funds: Vec<Coin>, recipient: String) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if !config.allowed_channels.contains(&channel) {
+        return Err(ContractError::ChannelNotAllowed { channel });
+    }
+    assert_no_funds(&info.funds.into_iter().map(Asset::from).collect::<Vec<_>>())?;
+    let assets: Vec<Asset> = funds.iter().cloned().map(Asset::from).collect();
+    if assets.is_empty() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "you must name the coins you wish to withdraw",
+        )));
+    }
+
+    let data = to_binary(&IbcExecuteMsg::WithdrawRemote {
+        funds,
+        sender: info.sender.to_string(),
+        recipient: recipient.clone(),
+    })?;
+    validate_packet_size(&config, &data)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "withdraw_remote")
+        .add_attribute("amount", join_assets(&assets))
+        .add_event(
+            event("withdraw_remote")
+                .add_attribute("channel", &channel)
+                .add_attribute("sender", info.sender)
+                .add_attribute("recipient", recipient)
+                .add_attributes(asset_attributes("", &assets)),
+        )
+        .add_message(IbcMsg::SendPacket {
+            channel_id: channel,
+            data,
+            timeout: IbcTimeout::with_timestamp(env.block.time.plus_seconds(config.ibc_hop_timeout)),
+        }))
+}
+
+/// Pays `funds` out of the remote account of `sender` on `channel` to `recipient`, only the
+/// sender can ask for it
+pub fn receive_withdraw_remote(deps: DepsMut, channel: String, sender: String, recipient: String, funds: Vec<Coin>) -> Result<IbcReceiveResponse, ContractError> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let assets: Vec<Asset> = funds.into_iter().map(Asset::from).collect();
+    // an error ack keeps what was written before it, the whole withdrawal is checked first
+    deduct(&remote_balance(deps.storage, &channel, &sender)?, &assets)?;
+    debit_remote(deps.storage, &channel, &sender, &assets)?;
+    let msgs = assets
+        .iter()
+        .map(|asset| asset.transfer_msg(recipient.as_str()))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(IbcReceiveResponse::new()
+        .add_attribute("method", "receive_withdraw_remote")
+        .add_attribute("channel", &channel)
+        .add_event(
+            event("remote_withdrawn")
+                .add_attribute("channel", channel)
+                .add_attribute("remote_sender", sender)
+                .add_attribute("recipient", recipient)
+                .add_attributes(asset_attributes("", &assets)),
+        )
+        .add_messages(msgs)
+        .set_ack(make_ack_success()))
+}
@@ -0,0 +1,142 @@
+use cosmwasm_std::{Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+use crate::admin::assert_not_paused;
+use crate::asset::Asset;
+use crate::events::{asset_attributes, event};
+use crate::fees::{assert_covers_keeper_tip, release, reserve, split_keeper_tip};
+use crate::msg::ExecuteMsg;
+use crate::pipeline::{start_pipeline, Caller};
+use crate::receipt::{assert_no_receipt, position_beneficiary};
+use crate::state::{Position, ScheduledPipeline, Trigger, CONFIG, DEFAULT_LIMIT, MAX_LIMIT, SCHEDULED, SCHEDULED_COUNT};
+use crate::validation::validate_commands;
+use crate::ContractError;
+
+/// Escrows `funds` until `trigger` is met. Only accounts can schedule, a pipeline step
+/// has nobody to give the escrow back to.
+pub fn schedule_pipeline(
+    deps: DepsMut,
+    env: Env,
+    caller: Caller,
+    funds: Vec<Asset>,
+    commands: Vec<ExecuteMsg>,
+    trigger: Trigger,
+) -> Result<Response, ContractError> {
+    let owner = caller.refund_address().ok_or(ContractError::Unauthorized {})?;
+    for command in &commands {
+        assert_not_paused(deps.storage, command)?;
+    }
+    validate_commands(deps.as_ref(), &env, &commands)?;
+    assert_covers_keeper_tip(&CONFIG.load(deps.storage)?, &funds)?;
+
+    let id = SCHEDULED_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    SCHEDULED_COUNT.save(deps.storage, &id)?;
+    for asset in &funds {
+        reserve(deps.storage, asset)?;
+    }
+    let scheduled = ScheduledPipeline {
+        id,
+        owner,
+        commands,
+        funds,
+        trigger,
+        created_at: env.block.time,
+    };
+    SCHEDULED.save(deps.storage, id, &scheduled)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "schedule_pipeline")
+        .add_attribute("id", id.to_string())
+        .add_event(
+            event("schedule")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", &scheduled.owner)
+                .add_attributes(asset_attributes("", &scheduled.funds)),
+        ))
+}
+
+/// Removes a scheduled pipeline and releases its escrow
+fn take_scheduled(storage: &mut dyn Storage, id: u64) -> StdResult<ScheduledPipeline> {
+    let scheduled = SCHEDULED.load(storage, id)?;
+    SCHEDULED.remove(storage, id);
+    for asset in &scheduled.funds {
+        release(storage, asset)?;
+    }
+    Ok(scheduled)
+}
+
+pub fn execute_scheduled(deps: DepsMut, env: Env, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let scheduled = SCHEDULED.load(deps.storage, id)?;
+    if !scheduled.trigger.is_met(&env.block) {
+        return Err(ContractError::TriggerNotMet { id });
+    }
+    // allowlists and limits may have changed since the pipeline was scheduled
+    validate_commands(deps.as_ref(), &env, &scheduled.commands)?;
+    let scheduled = take_scheduled(deps.storage, id)?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let (funds, tip) = split_keeper_tip(&config, scheduled.funds)?;
+    let tip_msgs = tip
+        .iter()
+        .map(|asset| asset.transfer_msg(info.sender.as_str()))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    // the pipeline runs for the owner, who gets back whatever it leaves unless a receipt
+    // of the position circulates
+    let caller = Caller::External(scheduled.owner.clone());
+    let beneficiary = position_beneficiary(deps.storage, &Position::Scheduled(id), &scheduled.owner)?;
+    let (pipeline_id, msgs) = start_pipeline(deps, &env, env.contract.address.clone(), scheduled.commands, funds, &caller, beneficiary)?;
+    let mut event = event("execute_scheduled")
+        .add_attribute("id", id.to_string())
+        .add_attribute("owner", &scheduled.owner)
+        .add_attribute("keeper", &info.sender)
+        .add_attributes(asset_attributes("tip_", &tip));
+    if let Some(pipeline_id) = pipeline_id {
+        event = event.add_attribute("pipeline_id", pipeline_id.to_string());
+    }
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_scheduled")
+        .add_attribute("id", id.to_string())
+        .add_event(event)
+        // the first step measures what it spends from the balance it started with, the
+        // tip leaves once the pipeline ran
+        .add_submessages(msgs)
+        .add_messages(tip_msgs))
+}
+
+pub fn cancel_scheduled(deps: DepsMut, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let scheduled = SCHEDULED.load(deps.storage, id)?;
+    if scheduled.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    assert_no_receipt(deps.storage, &Position::Scheduled(id))?;
+    let scheduled = take_scheduled(deps.storage, id)?;
+    let refunds = scheduled
+        .funds
+        .iter()
+        .map(|asset| asset.transfer_msg(scheduled.owner.as_str()))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(Response::new()
+        .add_attribute("method", "cancel_scheduled")
+        .add_attribute("id", id.to_string())
+        .add_event(
+            event("cancel_scheduled")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", &scheduled.owner)
+                .add_attributes(asset_attributes("", &scheduled.funds)),
+        )
+        .add_messages(refunds))
+}
+
+pub fn scheduled_pipelines(deps: Deps, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Vec<ScheduledPipeline>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    SCHEDULED
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, scheduled)| scheduled))
+        .collect()
+}
+
@@ -0,0 +1,148 @@
+use cosmwasm_std::{Deps, Env, StdResult, Uint128};
+use cw721_base::msg::QueryMsg as Cw721QueryMsg;
+use cw721::OwnerOfResponse;
+
+use crate::admin::assert_not_paused;
+use crate::asset::{deduct, Asset};
+use crate::condition::evaluate;
+use crate::fees::compute_charges;
+use crate::msg::{ExecuteMsg, SimulationResponse, StepSimulation};
+use crate::osmosis;
+use crate::state::{Config, CONFIG};
+use crate::validation::validate_commands;
+use crate::ContractError;
+
+/// Walks `commands` as a pipeline owning `funds`, reporting the first failed check
+/// instead of returning it
+pub fn simulate_pipeline(deps: Deps, env: &Env, commands: Vec<ExecuteMsg>, funds: Vec<Asset>) -> StdResult<SimulationResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut steps = vec![];
+    let (funds_left, error) = match validate_commands(deps, env, &commands) {
+        Ok(()) => match simulate_commands(deps, env, &config, &commands, funds, &mut steps) {
+            Ok(funds_left) => (funds_left, None),
+            Err((funds_left, err)) => (funds_left, Some(err.to_string())),
+        },
+        Err(err) => (funds, Some(err.to_string())),
+    };
+
+    Ok(SimulationResponse {
+        steps,
+        funds_left,
+        error,
+    })
+}
+
+/// Pushes the simulated steps, returns the funds left, or the funds before the failed
+/// step together with its error
+fn simulate_commands(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    commands: &[ExecuteMsg],
+    mut funds: Vec<Asset>,
+    steps: &mut Vec<StepSimulation>,
+) -> Result<Vec<Asset>, (Vec<Asset>, ContractError)> {
+    for command in commands {
+        // the branch an `If` chooses runs in its place, assuming every step succeeds
+        if let ExecuteMsg::If { condition, then, otherwise } = command {
+            let met = evaluate(deps, condition, &funds, false).map_err(|err| (funds.clone(), err.into()))?;
+            let branch = if met { then } else { otherwise };
+            funds = simulate_commands(deps, env, config, branch, funds, steps)?;
+            continue;
+        }
+        match simulate_step(deps, env, config, command, &funds) {
+            Ok((step, funds_after)) => {
+                funds = funds_after;
+                steps.push(step);
+            }
+            Err(err) => return Err((funds, err)),
+        }
+    }
+    Ok(funds)
+}
+
+fn simulate_step(
+    deps: Deps,
+    env: &Env,
+    config: &Config,
+    command: &ExecuteMsg,
+    funds: &[Asset],
+) -> Result<(StepSimulation, Vec<Asset>), ContractError> {
+    assert_not_paused(deps.storage, command)?;
+    let kind = command.kind().ok_or(ContractError::Unauthorized {})?;
+
+    // like a running pipeline, a command with an explicit asset only gets that asset
+    let input = command.input(funds);
+    let mut funds = deduct(funds, &input)?;
+
+    let referral_bps = command.referrer().map(|referrer| referrer.bps).unwrap_or_default();
+    let (assets, charges) = compute_charges(config, kind, referral_bps, input.clone())?;
+
+    let mut steps = vec![];
+    let output = match command {
+        ExecuteMsg::Swap { pool_id, token_out_denom, token_out_min_amount, .. } => {
+            let amount = estimate_swap(deps, env, &assets, *pool_id, token_out_denom)?;
+            if amount < *token_out_min_amount {
+                return Err(ContractError::SwapBelowMinimum {
+                    amount,
+                    min: *token_out_min_amount,
+                });
+            }
+            vec![Asset::native(token_out_denom, amount)]
+        }
+        // the shares asked for; a join with several coins may leave some of them unspent
+        ExecuteMsg::JoinPool { pool_id, share_out_amount, .. } => {
+            vec![Asset::native(osmosis::share_denom(*pool_id), *share_out_amount)]
+        }
+        ExecuteMsg::ExitPool { token_out_mins, .. } => token_out_mins.iter().cloned().map(Asset::from).collect(),
+        ExecuteMsg::PurchaseNFT { contract_addr, token_id, .. } => {
+            // purchases mint the token, which fails once it exists
+            let owner: StdResult<OwnerOfResponse> = deps.querier.query_wasm_smart(
+                contract_addr,
+                &Cw721QueryMsg::OwnerOf {
+                    token_id: token_id.clone(),
+                    include_expired: None,
+                },
+            );
+            if owner.is_ok() {
+                return Err(ContractError::TokenAlreadyMinted { token_id: token_id.clone() });
+            }
+            vec![]
+        }
+        // a hop into this contract runs its commands with the hop input,
+        // whatever they leave goes back to the pipeline
+        ExecuteMsg::ContractHop { contract_addr, commands, .. } if *contract_addr == env.contract.address.as_str() => {
+            simulate_commands(deps, env, config, commands, assets, &mut steps).map_err(|(_, err)| err)?
+        }
+        // transfers and hops elsewhere send their input away
+        _ => vec![],
+    };
+    for asset in &output {
+        add(&mut funds, asset);
+    }
+
+    let step = StepSimulation {
+        command: kind,
+        input,
+        fee: charges.protocol,
+        referral_fee: charges.referral,
+        output,
+        steps,
+    };
+    Ok((step, funds))
+}
+
+fn estimate_swap(deps: Deps, env: &Env, assets: &[Asset], pool_id: u64, token_out_denom: &str) -> Result<Uint128, ContractError> {
+    let token_in = match assets {
+        [asset] => asset.to_coin()?,
+        _ => return Err(ContractError::SingleAssetRequired {}),
+    };
+    Ok(osmosis::estimate_swap(deps, &env.contract.address, &token_in, pool_id, token_out_denom)?)
+}
+
+fn add(funds: &mut Vec<Asset>, asset: &Asset) {
+    match funds.iter_mut().find(|owned| owned.info == asset.info) {
+        Some(owned) => owned.amount += asset.amount,
+        None => funds.push(asset.clone()),
+    }
+}
@@ -0,0 +1,294 @@
+use cosmwasm_std::{
+    Addr, BankMsg, Coin, Decimal, Deps, DepsMut, DistributionMsg, Env, MessageInfo, Order, Response, StakingMsg, StdResult,
+    Storage, Uint128,
+};
+use cw_storage_plus::Bound;
+
+use crate::asset::{assert_no_funds, join_assets, Asset};
+use crate::events::{command_event, event};
+use crate::fees::{release, reserve};
+use crate::history::originator;
+use crate::incoming::{expect_incoming, take_incoming};
+use crate::msg::{CommandKind, DelegationResponse};
+use crate::pipeline::Caller;
+use crate::state::{
+    Delegation, Unbonding, ValidatorStake, CONFIG, DEFAULT_LIMIT, DELEGATIONS, MAX_LIMIT, UNBONDINGS, UNBONDING_COUNT,
+    VALIDATOR_STAKES,
+};
+use crate::ContractError;
+
+/// Stake of `owner` on `validator` with its rewards settled. Any staking message on the
+/// validator pays the contract all its rewards, they are reserved here and shared among
+/// the delegators by stake.
+fn settled_stake(
+    deps: DepsMut,
+    env: &Env,
+    denom: &str,
+    owner: &Addr,
+    validator: &str,
+) -> StdResult<(ValidatorStake, Delegation)> {
+    let mut stake = VALIDATOR_STAKES.may_load(deps.storage, validator)?.unwrap_or_default();
+    let rewards: Uint128 = deps
+        .querier
+        .query_delegation(&env.contract.address, validator)?
+        .map(|delegation| {
+            delegation
+                .accumulated_rewards
+                .iter()
+                .filter(|coin| coin.denom == denom)
+                .map(|coin| coin.amount)
+                .sum()
+        })
+        .unwrap_or_default();
+    if !rewards.is_zero() && !stake.bonded.is_zero() {
+        stake.rewards_per_share += Decimal::from_ratio(rewards, stake.bonded);
+        reserve(deps.storage, &Asset::native(denom, rewards))?;
+    }
+    VALIDATOR_STAKES.save(deps.storage, validator, &stake)?;
+
+    let mut delegation = DELEGATIONS
+        .may_load(deps.storage, (owner, validator))?
+        .unwrap_or_default();
+    delegation.pending_rewards += delegation.amount * (stake.rewards_per_share - delegation.reward_index);
+    delegation.reward_index = stake.rewards_per_share;
+    Ok((stake, delegation))
+}
+
+fn save_stake(
+    storage: &mut dyn Storage,
+    owner: &Addr,
+    validator: &str,
+    stake: &ValidatorStake,
+    delegation: &Delegation,
+) -> StdResult<()> {
+    VALIDATOR_STAKES.save(storage, validator, stake)?;
+    if delegation.amount.is_zero() && delegation.pending_rewards.is_zero() {
+        DELEGATIONS.remove(storage, (owner, validator));
+        Ok(())
+    } else {
+        DELEGATIONS.save(storage, (owner, validator), delegation)
+    }
+}
+
+fn staker(storage: &dyn Storage, caller: &Caller) -> Result<Addr, ContractError> {
+    originator(storage, caller)?.ok_or(ContractError::NoOriginator {})
+}
+
+pub fn execute_delegate(mut deps: DepsMut, env: Env, caller: Caller, assets: Vec<Asset>, validator: String) -> Result<Response, ContractError> {
+    let owner = staker(deps.storage, &caller)?;
+    let asset = match assets.as_slice() {
+        [asset] => asset.clone(),
+        _ => return Err(ContractError::SingleAssetRequired {}),
+    };
+    let denom = deps.querier.query_bonded_denom()?;
+    let coin = asset.to_coin()?;
+    if coin.denom != denom {
+        return Err(ContractError::FundsMismatch {
+            expected: denom,
+            received: asset.to_string(),
+        });
+    }
+
+    let (mut stake, mut delegation) = settled_stake(deps.branch(), &env, &denom, &owner, &validator)?;
+    stake.bonded += coin.amount;
+    delegation.amount += coin.amount;
+    save_stake(deps.storage, &owner, &validator, &stake, &delegation)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_delegate")
+        .add_event(
+            command_event(CommandKind::Delegate)
+                .add_attribute("validator", &validator)
+                .add_attribute("owner", &owner)
+                .add_attribute("amount", coin.to_string()),
+        )
+        .add_message(StakingMsg::Delegate {
+            validator,
+            amount: coin,
+        }))
+}
+
+pub fn execute_undelegate(
+    mut deps: DepsMut,
+    env: Env,
+    caller: Caller,
+    assets: Vec<Asset>,
+    validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    assert_no_funds(&assets)?;
+    let owner = staker(deps.storage, &caller)?;
+    let denom = deps.querier.query_bonded_denom()?;
+
+    let (mut stake, mut delegation) = settled_stake(deps.branch(), &env, &denom, &owner, &validator)?;
+    if delegation.amount < amount {
+        return Err(ContractError::InsufficientStake { validator, amount });
+    }
+    stake.bonded -= amount;
+    delegation.amount -= amount;
+    save_stake(deps.storage, &owner, &validator, &stake, &delegation)?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let id = UNBONDING_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    UNBONDING_COUNT.save(deps.storage, &id)?;
+    let unbonding = Unbonding {
+        id,
+        owner,
+        amount: Coin { denom, amount },
+        completes_at: env.block.time.plus_seconds(config.unbonding_period),
+    };
+    UNBONDINGS.save(deps.storage, id, &unbonding)?;
+    expect_incoming(deps.storage, unbonding.completes_at, &unbonding.source(), std::slice::from_ref(&unbonding.amount))?;
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_undelegate")
+        .add_event(
+            command_event(CommandKind::Undelegate)
+                .add_attribute("validator", &validator)
+                .add_attribute("owner", &unbonding.owner)
+                .add_attribute("amount", unbonding.amount.to_string())
+                .add_attribute("unbonding_id", id.to_string())
+                .add_attribute("completes_at", unbonding.completes_at.to_string()),
+        )
+        .add_message(StakingMsg::Undelegate {
+            validator,
+            amount: unbonding.amount,
+        }))
+}
+
+pub fn execute_redelegate(
+    mut deps: DepsMut,
+    env: Env,
+    caller: Caller,
+    assets: Vec<Asset>,
+    src_validator: String,
+    dst_validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    assert_no_funds(&assets)?;
+    let owner = staker(deps.storage, &caller)?;
+    let denom = deps.querier.query_bonded_denom()?;
+
+    // both validators pay out their rewards
+    let (mut src_stake, mut src) = settled_stake(deps.branch(), &env, &denom, &owner, &src_validator)?;
+    if src.amount < amount {
+        return Err(ContractError::InsufficientStake {
+            validator: src_validator,
+            amount,
+        });
+    }
+    src_stake.bonded -= amount;
+    src.amount -= amount;
+    save_stake(deps.storage, &owner, &src_validator, &src_stake, &src)?;
+
+    let (mut dst_stake, mut dst) = settled_stake(deps.branch(), &env, &denom, &owner, &dst_validator)?;
+    dst_stake.bonded += amount;
+    dst.amount += amount;
+    save_stake(deps.storage, &owner, &dst_validator, &dst_stake, &dst)?;
+
+    let amount = Coin { denom, amount };
+    Ok(Response::new()
+        .add_attribute("method", "execute_redelegate")
+        .add_event(
+            command_event(CommandKind::Redelegate)
+                .add_attribute("src_validator", &src_validator)
+                .add_attribute("dst_validator", &dst_validator)
+                .add_attribute("owner", &owner)
+                .add_attribute("amount", amount.to_string()),
+        )
+        .add_message(StakingMsg::Redelegate {
+            src_validator,
+            dst_validator,
+            amount,
+        }))
+}
+
+/// The rewards join the pipeline funds, or go to the sender when it called directly
+pub fn execute_claim_rewards(mut deps: DepsMut, env: Env, caller: Caller, assets: Vec<Asset>, validator: String) -> Result<Response, ContractError> {
+    assert_no_funds(&assets)?;
+    let owner = staker(deps.storage, &caller)?;
+    let denom = deps.querier.query_bonded_denom()?;
+
+    let (stake, mut delegation) = settled_stake(deps.branch(), &env, &denom, &owner, &validator)?;
+    let rewards = Asset::native(&denom, delegation.pending_rewards);
+    delegation.pending_rewards = Uint128::zero();
+    save_stake(deps.storage, &owner, &validator, &stake, &delegation)?;
+    if !rewards.amount.is_zero() {
+        release(deps.storage, &rewards)?;
+    }
+
+    let mut response = Response::new()
+        .add_attribute("method", "execute_claim_rewards")
+        .add_event(
+            command_event(CommandKind::ClaimRewards)
+                .add_attribute("validator", &validator)
+                .add_attribute("owner", &owner)
+                .add_attribute("rewards", rewards.to_string()),
+        );
+    // with no stake left the validator has nothing to pay, the rewards were settled before
+    if !stake.bonded.is_zero() {
+        response = response.add_message(DistributionMsg::WithdrawDelegatorReward { validator });
+    }
+    if let (Caller::External(sender), false) = (&caller, rewards.amount.is_zero()) {
+        response = response.add_message(rewards.transfer_msg(sender.as_str())?);
+    }
+    Ok(response)
+}
+
+pub fn claim_unbonded(deps: DepsMut, env: Env, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let unbonding = UNBONDINGS.load(deps.storage, id)?;
+    if unbonding.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    // the coins must be back and reserved for the owner, not paid out of what the
+    // contract holds for others. A slashed unbonding pays what came back.
+    let coins = match take_incoming(deps.storage, unbonding.completes_at, &unbonding.source())? {
+        Some(coins) if unbonding.completes_at <= env.block.time => coins,
+        _ => return Err(ContractError::UnbondingNotComplete { id }),
+    };
+    UNBONDINGS.remove(deps.storage, id);
+
+    let paid: Vec<Asset> = coins.iter().cloned().map(Asset::from).collect();
+    let mut response = Response::new()
+        .add_attribute("method", "claim_unbonded")
+        .add_attribute("id", id.to_string())
+        .add_event(
+            event("claim_unbonded")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", &unbonding.owner)
+                .add_attribute("amount", join_assets(&paid)),
+        );
+    if !coins.is_empty() {
+        response = response.add_message(BankMsg::Send {
+            to_address: unbonding.owner.into(),
+            amount: coins,
+        });
+    }
+    Ok(response)
+}
+
+/// Stake the contract delegated for `owner` over all validators
+pub fn staked(storage: &dyn Storage, owner: &Addr) -> StdResult<Uint128> {
+    DELEGATIONS
+        .prefix(owner)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, delegation)| delegation.amount))
+        .sum()
+}
+
+pub fn delegations(deps: Deps, owner: &Addr, start_after: Option<String>, limit: Option<u32>) -> StdResult<Vec<DelegationResponse>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+    DELEGATIONS
+        .prefix(owner)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(validator, delegation)| DelegationResponse {
+                validator,
+                amount: delegation.amount,
+                pending_rewards: delegation.pending_rewards,
+            })
+        })
+        .collect()
+}
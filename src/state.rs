@@ -1,9 +1,470 @@
-use cosmwasm_std::Addr;
+use cosmwasm_std::{Addr, BlockInfo, Coin, Decimal, Empty, Timestamp, Uint128, VoteOption};
+use cw20::Expiration;
 use cw_storage_plus::Item;
 use cw_storage_plus::Map;
-use crate::msg::ExecuteMsg;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-pub const COMMANDS_STACK: Item<Vec<ExecuteMsg>> = Item::new( "commands_stack");
-pub const CONTRACT_ADDRESS: Item<Addr> = Item::new( "contract_address");
+use crate::asset::Asset;
+use crate::msg::{CommandKind, ExecuteMsg, WeightedVoteOption};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub admin: Addr,
+    /// Seconds before an outgoing ICS-20 transfer times out
+    pub ibc_transfer_timeout: u64,
+    /// Seconds before an outgoing `IbcContractHop` packet times out
+    pub ibc_hop_timeout: u64,
+    /// Protocol fee, none when the contract is free to use
+    pub fee: Option<Fee>,
+    /// Fees replacing `fee` for some command types
+    pub command_fees: Vec<CommandFee>,
+    /// Contracts pipelines may hop into besides this one
+    pub allowed_targets: Vec<Addr>,
+    /// Channels `IbcContractHop` may send packets on
+    pub allowed_channels: Vec<String>,
+    /// Osmosis pools `Swap` may trade in
+    pub allowed_pools: Vec<u64>,
+    /// Contracts `WasmExecute` may call
+    pub allowed_contracts: Vec<Addr>,
+    /// Message types `StargateMsg` may send
+    pub allowed_type_urls: Vec<String>,
+    /// cw20-ics20 contract used to bridge CW20 tokens over IBC
+    pub cw20_ics20_contract: Option<Addr>,
+    /// Highest share a referrer may ask for
+    pub max_referral_bps: u16,
+    pub referral_mode: ReferralMode,
+    /// How deep hops may be nested in a command
+    pub max_depth: u32,
+    /// Most commands a single pipeline may hold, nested ones included
+    pub max_commands: u32,
+    /// Largest `IbcContractHop` packet sent or accepted, in bytes
+    pub max_packet_size: u64,
+    /// Paid out of the escrow to whoever runs a scheduled pipeline
+    pub keeper_tip: Option<Fee>,
+    /// Seconds undelegated stake takes to come back, the staking unbonding time of the chain
+    pub unbonding_period: u64,
+}
+
+/// Where the referrer share comes from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferralMode {
+    /// The referrer gets `bps` of the protocol fee
+    #[default]
+    FromFee,
+    /// The referrer gets `bps` of the command input on top of the protocol fee
+    OnTop,
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Fee {
+    /// Fixed amount taken from the command input
+    Flat(Asset),
+    /// Share of the command input in basis points
+    Bps(u16),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CommandFee {
+    pub command: CommandKind,
+    pub fee: Fee,
+}
+
+/// Capabilities the admin stopped, everything runs by default
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseFlags {
+    /// `Transfer` and `IbcTransfer`
+    pub transfers: bool,
+    /// `Swap`, `JoinPool`, `ExitPool`, `LockTokens`, `SuperfluidDelegate` and `Unlock`
+    pub swaps: bool,
+    pub nft: bool,
+    /// `ContractHop`, `IbcContractHop`, `WasmExecute` and `StargateMsg`
+    pub hops: bool,
+    /// Packets received from counterparty contracts
+    pub ibc_receive: bool,
+    /// `Delegate`, `Undelegate`, `Redelegate` and `ClaimRewards`
+    #[serde(default)]
+    pub staking: bool,
+    /// `Vote` and `VoteWeighted`
+    #[serde(default)]
+    pub governance: bool,
+}
+
+/// Commands of a `ContractHop` that still have to run, in execution order
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Pipeline {
+    /// Key of the pipeline in `PIPELINE_HISTORY`
+    pub id: u64,
+    pub contract_addr: Addr,
+    pub commands: Vec<ExecuteMsg>,
+    /// Funds the pipeline owns, the only ones its steps may spend
+    #[serde(default)]
+    pub funds: Vec<Asset>,
+    /// Contract balances before the running step
+    #[serde(default)]
+    pub balances_before: Vec<Asset>,
+    /// `RESERVED` before the running step, by asset key
+    #[serde(default)]
+    pub reserved_before: Vec<(String, Uint128)>,
+    /// Gets the funds left when the pipeline finishes
+    #[serde(default)]
+    pub beneficiary: Beneficiary,
+    /// Outcome of the last step, for the `If` commands after it
+    #[serde(default)]
+    pub previous_step_failed: bool,
+    /// Funds handed to the running step
+    #[serde(default)]
+    pub step_input: Vec<Asset>,
+}
+
+/// Who gets the funds a pipeline leaves
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Beneficiary {
+    /// Nested hop, what it leaves counts for the pipeline whose step started it
+    #[default]
+    Pipeline,
+    /// Account the pipeline runs for
+    Account(Addr),
+    /// Remote account of `sender` on `channel`, for pipelines received over IBC
+    Remote { channel: String, sender: String },
+    /// Proceeds of the receipt with this subdenom, paid to whoever redeems it
+    Receipt(String),
+}
+
+/// What a pipeline did, kept after it finishes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PipelineRecord {
+    pub id: u64,
+    /// Pipeline whose `ContractHop` started this one
+    pub parent_id: Option<u64>,
+    /// Account the pipeline runs for, none for pipelines received over IBC
+    pub sender: Option<Addr>,
+    /// Channel the pipeline was received on
+    pub channel: Option<String>,
+    /// Account on the other end of `channel` the pipeline runs for
+    #[serde(default)]
+    pub remote_sender: Option<String>,
+    pub contract_addr: Addr,
+    pub commands: Vec<ExecuteMsg>,
+    /// Funds the pipeline started with
+    pub funds: Vec<Asset>,
+    pub steps: Vec<StepRecord>,
+    pub status: PipelineStatus,
+    pub started_at: Timestamp,
+    pub finished_at: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStatus {
+    Running,
+    Completed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StepRecord {
+    pub command: CommandKind,
+    pub status: StepStatus,
+    /// Assets sent to the step
+    pub input: Vec<Asset>,
+    /// Assets the step left in the contract
+    pub output: Vec<Asset>,
+    /// Sequence of the packet an `IbcContractHop` step sent, known once it is acknowledged
+    pub ibc_sequence: Option<u64>,
+    /// Error the counterparty acknowledged the packet with
+    pub error: Option<String>,
+    pub started_at: Timestamp,
+    pub finished_at: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Running,
+    Succeeded,
+    /// `IbcContractHop` packet waiting for its acknowledgement
+    PacketSent,
+    /// The counterparty ran the packet
+    Acknowledged,
+    /// The step failed and was undone, or the counterparty refused its packet
+    Failed,
+    TimedOut,
+}
+
+impl StepStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StepStatus::Running => "running",
+            StepStatus::Succeeded => "succeeded",
+            StepStatus::PacketSent => "packet_sent",
+            StepStatus::Acknowledged => "acknowledged",
+            StepStatus::Failed => "failed",
+            StepStatus::TimedOut => "timed_out",
+        }
+    }
+}
+
+/// When a scheduled pipeline may run
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// From this block height on
+    AtHeight(u64),
+    /// From this time on
+    AtTime(Timestamp),
+}
+
+impl Trigger {
+    pub fn is_met(&self, block: &BlockInfo) -> bool {
+        match self {
+            Trigger::AtHeight(height) => block.height >= *height,
+            Trigger::AtTime(time) => block.time >= *time,
+        }
+    }
+}
+
+/// Pipeline waiting for its trigger, its funds are held in escrow
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ScheduledPipeline {
+    pub id: u64,
+    pub owner: Addr,
+    pub commands: Vec<ExecuteMsg>,
+    pub funds: Vec<Asset>,
+    pub trigger: Trigger,
+    pub created_at: Timestamp,
+}
+
+/// Page sizes of list queries
+pub const DEFAULT_LIMIT: u32 = 10;
+pub const MAX_LIMIT: u32 = 30;
+
+/// Pool a swap goes through and the denom it gives
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SwapRoute {
+    pub pool_id: u64,
+    pub token_out_denom: String,
+}
+
+/// Where a DCA sends the output of its swaps
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DcaDestination {
+    Address(String),
+    Ibc { channel_id: String, address: String },
+}
+
+/// Recurring swap, what is left to swap is held in escrow
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Dca {
+    pub id: u64,
+    pub owner: Addr,
+    pub pool_routes: Vec<SwapRoute>,
+    pub amount_per_interval: Uint128,
+    pub interval_seconds: u64,
+    pub remaining_occurrences: u32,
+    pub destination: Option<DcaDestination>,
+    /// Least output of the last route per coin of `amount_per_interval`, none for any
+    pub min_price: Option<Decimal>,
+    /// What is left to swap
+    pub escrow: Asset,
+    /// The next swap may run from then on
+    pub next_run: Timestamp,
+    pub created_at: Timestamp,
+}
+
+/// Osmosis lock the contract holds for an account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Lock {
+    /// Osmosis lock id, zero until the lock message replied
+    pub id: u64,
+    pub owner: Addr,
+    pub coins: Vec<Coin>,
+    /// Seconds the coins take to unlock
+    pub duration: u64,
+    /// Validator the coins are superfluid delegated to
+    pub validator: Option<String>,
+    pub created_at: Timestamp,
+    /// When the coins are back with the contract, set once unlocking started
+    pub unlocks_at: Option<Timestamp>,
+}
+
+impl Lock {
+    /// Key of its coins among the incoming ones, e.g. `lock-7`
+    pub fn source(&self) -> String {
+        format!("lock-{}", self.id)
+    }
+}
+
+/// Stake the contract delegated to a validator for an account
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Delegation {
+    pub amount: Uint128,
+    /// `ValidatorStake::rewards_per_share` when the rewards were last settled
+    pub reward_index: Decimal,
+    /// Settled rewards, reserved until they are claimed
+    pub pending_rewards: Uint128,
+}
+
+/// Stake of every account delegating to a validator through the contract. Rewards are
+/// paid in the staking coin.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct ValidatorStake {
+    pub bonded: Uint128,
+    /// Rewards paid per unit of stake since the first delegation
+    pub rewards_per_share: Decimal,
+}
+
+/// Vote of a staker on a proposal with the stake it was counted with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakerVote {
+    pub options: Vec<WeightedVoteOption>,
+    pub stake: Uint128,
+}
+
+/// Stake the stakers voting through the contract put behind an option
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OptionTally {
+    pub option: VoteOption,
+    pub stake: Uint128,
+}
+
+/// Undelegated coins on their way back to the contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Unbonding {
+    pub id: u64,
+    pub owner: Addr,
+    pub amount: Coin,
+    pub completes_at: Timestamp,
+}
+
+impl Unbonding {
+    /// Key of its coins among the incoming ones, e.g. `unbonding-4`
+    pub fn source(&self) -> String {
+        format!("unbonding-{}", self.id)
+    }
+}
+
+/// Last block incoming coins were settled in. The chain pays back what is due at the end
+/// of the first block at or past the due time, so all that was due by the time of an
+/// earlier block is back.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SettleBlock {
+    pub height: u64,
+    pub time: Timestamp,
+    /// Time of the block settled in before `height`, everything due by then is back
+    pub paid_until: Timestamp,
+}
+
+/// Escrowed position a receipt token stands for. Funds waiting for an IBC acknowledgement
+/// are no position: they already left the contract or sit in the remote account of a
+/// counterparty sender.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    Scheduled(u64),
+    Dca(u64),
+    LimitOrder(u64),
+}
+
+impl Position {
+    /// Token factory subdenom of the receipt, e.g. `dca-3`
+    pub fn subdenom(&self) -> String {
+        match self {
+            Position::Scheduled(id) => format!("scheduled-{}", id),
+            Position::Dca(id) => format!("dca-{}", id),
+            Position::LimitOrder(id) => format!("limit-order-{}", id),
+        }
+    }
+}
+
+/// Token factory token standing for a position, a single unit of `denom`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Receipt {
+    pub position: Position,
+    pub denom: String,
+    /// Whether the token is in circulation. Owner-only actions on the position wait until
+    /// it is redeemed.
+    pub outstanding: bool,
+    /// What runs of the position left while the token circulated, reserved for whoever
+    /// redeems it
+    #[serde(default)]
+    pub proceeds: Vec<Asset>,
+}
+
+/// Swap waiting for its price, the offered coin is held in escrow
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LimitOrder {
+    pub id: u64,
+    pub owner: Addr,
+    pub pool_id: u64,
+    pub offer: Asset,
+    pub token_out_denom: String,
+    /// Lowest amount of `token_out_denom` per unit of the offered coin
+    pub min_price: Decimal,
+    pub expiry: Expiration,
+    pub created_at: Timestamp,
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+/// Address the admin role was offered to, until it accepts it
+pub const PENDING_OWNER: Item<Addr> = Item::new("pending_owner");
+pub const PAUSED: Item<PauseFlags> = Item::new("paused");
+/// Protocol fees kept by the contract, keyed by asset info
+pub const TREASURY: Map<String, Asset> = Map::new("treasury");
+/// Referral fees each referrer can claim, keyed by referrer and asset info
+pub const REFERRAL_BALANCES: Map<(&Addr, String), Asset> = Map::new("referral_balances");
+/// Funds the packets of a counterparty account may spend, keyed by channel, sender on the
+/// other end and asset info
+pub const REMOTE_BALANCES: Map<(&str, &str, String), Asset> = Map::new("remote_balances");
+/// Total the contract holds on behalf of someone per asset info, kept apart from pipeline funds
+pub const RESERVED: Map<String, Uint128> = Map::new("reserved");
+/// Running pipelines, the last one belongs to the innermost `ContractHop`
+pub const PIPELINES: Item<Vec<Pipeline>> = Item::new("pipelines");
+/// Last id given to a pipeline
+pub const PIPELINE_COUNT: Item<u64> = Item::new("pipeline_count");
+pub const PIPELINE_HISTORY: Map<u64, PipelineRecord> = Map::new("pipeline_history");
+/// Last id given to a scheduled pipeline
+pub const SCHEDULED_COUNT: Item<u64> = Item::new("scheduled_count");
+pub const SCHEDULED: Map<u64, ScheduledPipeline> = Map::new("scheduled");
+/// Last id given to a DCA
+pub const DCA_COUNT: Item<u64> = Item::new("dca_count");
+pub const DCAS: Map<u64, Dca> = Map::new("dcas");
+/// Last id given to a limit order
+pub const LIMIT_ORDER_COUNT: Item<u64> = Item::new("limit_order_count");
+pub const LIMIT_ORDERS: Map<u64, LimitOrder> = Map::new("limit_orders");
+pub const LOCKS: Map<u64, Lock> = Map::new("locks");
+/// Lock whose message is waiting for the reply carrying its id
+pub const PENDING_LOCK: Item<Lock> = Item::new("pending_lock");
+/// Stake per account and validator
+pub const DELEGATIONS: Map<(&Addr, &str), Delegation> = Map::new("delegations");
+pub const VALIDATOR_STAKES: Map<&str, ValidatorStake> = Map::new("validator_stakes");
+/// Vote of each staker per proposal, the contract casts them combined
+pub const VOTES: Map<(u64, &Addr), StakerVote> = Map::new("votes");
+/// Stake behind each option per proposal, over the votes in `VOTES`
+pub const TALLIES: Map<u64, Vec<OptionTally>> = Map::new("tallies");
+/// Last id given to an unbonding
+pub const UNBONDING_COUNT: Item<u64> = Item::new("unbonding_count");
+pub const UNBONDINGS: Map<u64, Unbonding> = Map::new("unbondings");
+/// Coins on their way back to the contract that are not reserved yet, keyed by the
+/// second they are due and what they come from
+pub const INCOMING: Map<(u64, &str), Vec<Coin>> = Map::new("incoming");
+/// Coins reserved for `INCOMING` entries that came back, in full or short, until their
+/// owner takes them
+pub const SETTLED: Map<(u64, &str), Vec<Coin>> = Map::new("settled");
+pub const SETTLE_BLOCK: Item<SettleBlock> = Item::new("settle_block");
+/// Receipts ever minted, keyed by subdenom
+pub const RECEIPTS: Map<&str, Receipt> = Map::new("receipts");
+/// Ids of the pipelines each account started
+pub const PIPELINES_BY_SENDER: Map<(&Addr, u64), ()> = Map::new("pipelines_by_sender");
 // Mapping between connections and the counter on that connection.
 pub const CONNECTION_COUNTS: Map<String, u32> = Map::new("connection_counts");
+
+/// Pipeline layout of 0.1.0, only cleared by `migrate`. Commands were kept in reverse
+/// order and a nested hop overwrote the outer one. They are only counted, `Empty` reads
+/// any of them whatever the command looked like then.
+pub const COMMANDS_STACK: Item<Vec<Empty>> = Item::new( "commands_stack");
+pub const CONTRACT_ADDRESS: Item<Addr> = Item::new( "contract_address");
@@ -0,0 +1,148 @@
+use cosmwasm_std::{to_binary, Binary, Decimal, Deps, Env, StdError};
+
+use crate::gov::validate_vote_options;
+use crate::msg::{AmountSpec, ExecuteMsg, IbcExecuteMsg, PacketOrigin};
+use crate::state::{Config, CONFIG};
+use crate::ContractError;
+
+/// Checks a command and everything nested in it as soon as the message arrives,
+/// so malformed pipelines are rejected before any step runs.
+pub fn validate_command(deps: Deps, env: &Env, msg: &ExecuteMsg) -> Result<(), ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    check_command(deps, env, &config, msg, 0, &mut 0)
+}
+
+/// Checks the commands of a pipeline that starts without an enclosing hop, e.g. one
+/// received over IBC
+pub fn validate_commands(deps: Deps, env: &Env, commands: &[ExecuteMsg]) -> Result<(), ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    check_commands(deps, env, &config, commands, 0, &mut 0)
+}
+
+/// Fails for `IbcContractHop` packets above the configured size, before they are parsed
+/// or sent
+pub fn validate_packet_size(config: &Config, data: &Binary) -> Result<(), ContractError> {
+    if data.len() as u64 > config.max_packet_size {
+        return Err(ContractError::PacketTooLarge {
+            size: data.len(),
+            max: config.max_packet_size,
+        });
+    }
+    Ok(())
+}
+
+/// `count` adds up the commands of the whole tree checked so far, nested ones included
+fn check_commands(deps: Deps, env: &Env, config: &Config, commands: &[ExecuteMsg], depth: u32, count: &mut usize) -> Result<(), ContractError> {
+    if depth > config.max_depth {
+        return Err(ContractError::PipelineTooDeep { max: config.max_depth });
+    }
+    *count += commands.len();
+    if *count > config.max_commands as usize {
+        return Err(ContractError::TooManyCommands {
+            count: *count,
+            max: config.max_commands,
+        });
+    }
+    for command in commands {
+        check_command(deps, env, config, command, depth, count)?;
+    }
+    Ok(())
+}
+
+/// `depth` counts the hops enclosing `msg`
+fn check_command(deps: Deps, env: &Env, config: &Config, msg: &ExecuteMsg, depth: u32, count: &mut usize) -> Result<(), ContractError> {
+    if let Some(referrer) = msg.referrer() {
+        deps.api.addr_validate(&referrer.address)?;
+        if referrer.bps > config.max_referral_bps {
+            return Err(ContractError::ReferralTooHigh {
+                bps: referrer.bps,
+                max: config.max_referral_bps,
+            });
+        }
+    }
+
+    if let Some(asset) = msg.asset() {
+        if asset.amount.is_zero() {
+            return Err(ContractError::ZeroAmount { field: "asset.amount".to_string() });
+        }
+    }
+
+    match msg {
+        // Osmosis rejects swaps without a positive minimum output
+        ExecuteMsg::Swap { token_out_min_amount, .. } if token_out_min_amount.is_zero() => {
+            return Err(ContractError::ZeroAmount { field: "token_out_min_amount".to_string() });
+        }
+        ExecuteMsg::Swap { pool_id, .. }
+        | ExecuteMsg::JoinPool { pool_id, .. }
+        | ExecuteMsg::ExitPool { pool_id, .. }
+            if !config.allowed_pools.contains(pool_id) =>
+        {
+            return Err(ContractError::PoolNotAllowed { pool_id: *pool_id });
+        }
+        ExecuteMsg::LockTokens { duration: 0, .. } => {
+            return Err(ContractError::ZeroAmount { field: "duration".to_string() });
+        }
+        ExecuteMsg::Undelegate { amount, .. } | ExecuteMsg::Redelegate { amount, .. } if amount.is_zero() => {
+            return Err(ContractError::ZeroAmount { field: "amount".to_string() });
+        }
+        ExecuteMsg::VoteWeighted { options, .. } => validate_vote_options(options)?,
+        ExecuteMsg::StargateMsg { type_url, .. } if !config.allowed_type_urls.contains(type_url) => {
+            return Err(ContractError::TypeUrlNotAllowed { type_url: type_url.clone() });
+        }
+        ExecuteMsg::WasmExecute { contract_addr, funds, .. } => {
+            let contract = deps.api.addr_validate(contract_addr)?;
+            if contract == env.contract.address || !config.allowed_contracts.contains(&contract) {
+                return Err(ContractError::ContractNotAllowed { contract: contract.into_string() });
+            }
+            match funds {
+                AmountSpec::Exact(assets) if assets.iter().any(|asset| asset.amount.is_zero()) => {
+                    return Err(ContractError::ZeroAmount { field: "funds".to_string() });
+                }
+                AmountSpec::Share(share) if share.is_zero() || *share > Decimal::one() => {
+                    return Err(ContractError::Std(StdError::generic_err(
+                        "funds share must be above zero and at most one",
+                    )));
+                }
+                _ => {}
+            }
+        }
+        ExecuteMsg::JoinPool { share_out_amount, .. } if share_out_amount.is_zero() => {
+            return Err(ContractError::ZeroAmount { field: "share_out_amount".to_string() });
+        }
+        ExecuteMsg::ContractHop { contract_addr, commands, .. } => {
+            // the hop target receives every command together with the pipeline funds
+            let target = deps.api.addr_validate(contract_addr)?;
+            if target != env.contract.address && !config.allowed_targets.contains(&target) {
+                return Err(ContractError::TargetNotAllowed { target: target.into_string() });
+            }
+            check_commands(deps, env, config, commands, depth + 1, count)?;
+        }
+        ExecuteMsg::If { then, otherwise, .. } => {
+            // branches run in the same pipeline, only hops go deeper
+            check_commands(deps, env, config, then, depth, count)?;
+            check_commands(deps, env, config, otherwise, depth, count)?;
+        }
+        ExecuteMsg::IbcContractHop { channel, commands, remote_funds, .. } => {
+            if !config.allowed_channels.contains(channel) {
+                return Err(ContractError::ChannelNotAllowed { channel: channel.clone() });
+            }
+            // the counterparty applies the same limit, better to fail here than in an error
+            // ack. The origin is sized for any step, the sender is only known when the
+            // packet is sent and measured again then.
+            let packet = to_binary(&IbcExecuteMsg::IbcContractHop {
+                commands: commands.clone(),
+                funds: remote_funds.clone(),
+                sender: None,
+                origin: Some(PacketOrigin {
+                    pipeline_id: u64::MAX,
+                    step: u32::MAX,
+                }),
+            })?;
+            validate_packet_size(config, &packet)?;
+            check_commands(deps, env, config, commands, depth + 1, count)?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
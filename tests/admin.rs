@@ -0,0 +1,178 @@
+mod common;
+
+use blazarbit_protocol::admin::ConfigUpdate;
+use blazarbit_protocol::msg::{ExecuteMsg, InstantiateMsg, OptionalSetting, QueryMsg};
+use blazarbit_protocol::state::{Config, Fee};
+use common::*;
+use cosmwasm_std::{Binary, VoteOption};
+
+fn pause_hops() -> ExecuteMsg {
+    ExecuteMsg::SetPause {
+        transfers: None,
+        swaps: None,
+        nft: None,
+        hops: Some(true),
+        ibc_receive: None,
+        staking: None,
+        governance: None,
+    }
+}
+
+#[test]
+fn paused_hops_stop_stargate_messages() {
+    let type_url = "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn".to_string();
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        allowed_type_urls: vec![type_url.clone()],
+        ..instantiate_msg()
+    });
+    run(&mut app, ADMIN, &contract, &pause_hops(), &[]).unwrap();
+
+    let stargate = ExecuteMsg::StargateMsg {
+        type_url,
+        value: Binary::default(),
+    };
+    let err = run(&mut app, ALICE, &contract, &stargate, &[]).unwrap_err();
+    assert_eq!(err, "hops are paused");
+}
+
+#[test]
+fn paused_governance_stops_votes() {
+    let (mut app, contract) = setup();
+    let pause = ExecuteMsg::SetPause {
+        transfers: None,
+        swaps: None,
+        nft: None,
+        hops: None,
+        ibc_receive: None,
+        staking: None,
+        governance: Some(true),
+    };
+    run(&mut app, ADMIN, &contract, &pause, &[]).unwrap();
+
+    let vote = ExecuteMsg::Vote {
+        proposal_id: 1,
+        option: VoteOption::Yes,
+    };
+    let err = run(&mut app, ALICE, &contract, &vote, &[]).unwrap_err();
+    assert_eq!(err, "governance votes are paused");
+}
+
+#[test]
+fn paused_swaps_stop_unlocking() {
+    let (mut app, contract) = setup();
+    let pause = ExecuteMsg::SetPause {
+        transfers: None,
+        swaps: Some(true),
+        nft: None,
+        hops: None,
+        ibc_receive: None,
+        staking: None,
+        governance: None,
+    };
+    run(&mut app, ADMIN, &contract, &pause, &[]).unwrap();
+
+    let err = run(&mut app, ALICE, &contract, &ExecuteMsg::Unlock { lock_id: 1 }, &[]).unwrap_err();
+    assert_eq!(err, "swaps are paused");
+}
+
+fn update_config(update: ConfigUpdate) -> ExecuteMsg {
+    ExecuteMsg::UpdateConfig {
+        ibc_transfer_timeout: update.ibc_transfer_timeout,
+        ibc_hop_timeout: update.ibc_hop_timeout,
+        fee: update.fee,
+        command_fees: update.command_fees,
+        allowed_targets: update.allowed_targets,
+        cw20_ics20_contract: update.cw20_ics20_contract,
+        max_referral_bps: update.max_referral_bps,
+        referral_mode: update.referral_mode,
+        max_depth: update.max_depth,
+        max_commands: update.max_commands,
+        max_packet_size: update.max_packet_size,
+        keeper_tip: update.keeper_tip,
+        unbonding_period: update.unbonding_period,
+        clear: update.clear,
+    }
+}
+
+#[test]
+fn update_config_clears_optional_settings() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        fee: Some(Fee::Bps(30)),
+        keeper_tip: Some(Fee::Bps(10)),
+        ..instantiate_msg()
+    });
+
+    let both = update_config(ConfigUpdate {
+        fee: Some(Fee::Bps(20)),
+        clear: vec![OptionalSetting::Fee],
+        ..ConfigUpdate::default()
+    });
+    let err = run(&mut app, ADMIN, &contract, &both, &[]).unwrap_err();
+    assert_eq!(err, "fee cannot be set and cleared at once");
+
+    let clear = update_config(ConfigUpdate {
+        clear: vec![OptionalSetting::Fee, OptionalSetting::KeeperTip],
+        ..ConfigUpdate::default()
+    });
+    run(&mut app, ADMIN, &contract, &clear, &[]).unwrap();
+    let config: Config = app.wrap().query_wasm_smart(&contract, &QueryMsg::Config {}).unwrap();
+    assert_eq!(config.fee, None);
+    assert_eq!(config.keeper_tip, None);
+}
+
+#[test]
+fn update_config_bounds_limits_and_timeouts() {
+    let (mut app, contract) = setup();
+    let cases = [
+        (
+            update_config(ConfigUpdate {
+                max_depth: Some(0),
+                ..ConfigUpdate::default()
+            }),
+            "max_depth must be between 1 and 8",
+        ),
+        (
+            update_config(ConfigUpdate {
+                ibc_hop_timeout: Some(0),
+                ..ConfigUpdate::default()
+            }),
+            "ibc_hop_timeout must be between 60 and 604800",
+        ),
+        (
+            update_config(ConfigUpdate {
+                unbonding_period: Some(0),
+                ..ConfigUpdate::default()
+            }),
+            "unbonding_period must be greater than zero",
+        ),
+    ];
+    for (msg, expected) in cases {
+        let err = run(&mut app, ADMIN, &contract, &msg, &[]).unwrap_err();
+        assert_eq!(err, expected);
+    }
+}
+
+#[test]
+fn ownership_moves_only_once_the_new_owner_accepts() {
+    let (mut app, contract) = setup();
+    let propose = ExecuteMsg::ProposeNewOwner {
+        owner: BOB.to_string(),
+    };
+    let err = run(&mut app, ATTACKER, &contract, &propose, &[]).unwrap_err();
+    assert_eq!(err, "only the contract admin can do this");
+    let err = run(&mut app, ATTACKER, &contract, &update_config(ConfigUpdate::default()), &[]).unwrap_err();
+    assert_eq!(err, "only the contract admin can do this");
+
+    run(&mut app, ADMIN, &contract, &propose, &[]).unwrap();
+    // the old admin stays in charge until the proposal is accepted
+    let config: Config = app.wrap().query_wasm_smart(&contract, &QueryMsg::Config {}).unwrap();
+    assert_eq!(config.admin, ADMIN);
+    let err = run(&mut app, ATTACKER, &contract, &ExecuteMsg::AcceptOwnership {}, &[]).unwrap_err();
+    assert_eq!(err, "Unauthorized");
+
+    run(&mut app, BOB, &contract, &ExecuteMsg::AcceptOwnership {}, &[]).unwrap();
+    let config: Config = app.wrap().query_wasm_smart(&contract, &QueryMsg::Config {}).unwrap();
+    assert_eq!(config.admin, BOB);
+    run(&mut app, ADMIN, &contract, &update_config(ConfigUpdate::default()), &[]).unwrap_err();
+    run(&mut app, BOB, &contract, &update_config(ConfigUpdate::default()), &[]).unwrap();
+}
@@ -0,0 +1,145 @@
+#![allow(dead_code)]
+
+use blazarbit_protocol::contract::{execute, instantiate, migrate, query, reply};
+use blazarbit_protocol::msg::{ExecuteMsg, InstantiateMsg};
+use blazarbit_protocol::state::ReferralMode;
+use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    coins, from_slice, Addr, Coin, ContractResult, Decimal, Empty, OwnedDeps, Querier, QuerierResult, QuerierWrapper,
+    QueryRequest, SystemResult, Uint128,
+};
+use cw_multi_test::{App, AppResponse, Contract, ContractWrapper, Executor};
+use cw_osmo_proto::osmosis::gamm::v1beta1::{QuerySpotPriceRequest, QuerySpotPriceResponse};
+use prost::Message;
+use std::marker::PhantomData;
+
+pub const ADMIN: &str = "admin";
+pub const ALICE: &str = "alice";
+pub const BOB: &str = "bob";
+pub const KEEPER: &str = "keeper";
+pub const ATTACKER: &str = "attacker";
+
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(
+        ContractWrapper::new(execute, instantiate, query)
+            .with_reply(reply)
+            .with_migrate(migrate),
+    )
+}
+
+pub fn instantiate_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        admin: Some(ADMIN.to_string()),
+        ibc_transfer_timeout: None,
+        ibc_hop_timeout: None,
+        fee: None,
+        command_fees: vec![],
+        allowed_targets: vec![],
+        allowed_channels: vec![],
+        allowed_pools: vec![],
+        allowed_contracts: vec![],
+        allowed_type_urls: vec![],
+        cw20_ics20_contract: None,
+        max_referral_bps: 0,
+        referral_mode: ReferralMode::default(),
+        max_depth: None,
+        max_commands: None,
+        max_packet_size: None,
+        keeper_tip: None,
+        unbonding_period: None,
+    }
+}
+
+/// App where ALICE and ATTACKER hold uosmo and uatom, with the contract instantiated by `msg`
+pub fn setup_with(msg: InstantiateMsg) -> (App, Addr) {
+    let mut app = App::new(|router, _, storage| {
+        for account in [ALICE, ATTACKER] {
+            router
+                .bank
+                .init_balance(
+                    storage,
+                    &Addr::unchecked(account),
+                    vec![Coin::new(10_000, "uatom"), Coin::new(10_000, "uosmo")],
+                )
+                .unwrap();
+        }
+    });
+    let code_id = app.store_code(contract());
+    let contract = app
+        .instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], "blazarbit", None)
+        .unwrap();
+    (app, contract)
+}
+
+pub fn setup() -> (App, Addr) {
+    setup_with(instantiate_msg())
+}
+
+/// Executes `msg`, failing with the message of the error that caused it
+pub fn run(app: &mut impl Executor<Empty>, sender: &str, contract: &Addr, msg: &ExecuteMsg, funds: &[Coin]) -> Result<AppResponse, String> {
+    app.execute_contract(Addr::unchecked(sender), contract.clone(), msg, funds)
+        .map_err(|err| err.root_cause().to_string())
+}
+
+pub fn balance(app: &impl Querier, address: &str, denom: &str) -> Uint128 {
+    QuerierWrapper::<Empty>::new(app).query_balance(address, denom).unwrap().amount
+}
+
+pub fn osmo(amount: u128) -> Vec<Coin> {
+    coins(amount, "uosmo")
+}
+
+/// `ContractHop` back into the contract running `commands`
+pub fn hop(contract: &Addr, commands: Vec<ExecuteMsg>) -> ExecuteMsg {
+    ExecuteMsg::ContractHop {
+        contract_addr: contract.to_string(),
+        commands,
+        referrer: None,
+    }
+}
+
+pub fn transfer(address: &str) -> ExecuteMsg {
+    ExecuteMsg::Transfer {
+        address: address.to_string(),
+        asset: None,
+        referrer: None,
+    }
+}
+
+/// Mock querier with a pool holding `pool`, weighted equally. It answers spot price
+/// queries the way Osmosis does: how much of `token_in_denom` pays for a unit of
+/// `token_out_denom`.
+pub struct PoolQuerier {
+    pub base: MockQuerier,
+    pub pool: Vec<Coin>,
+}
+
+impl Querier for PoolQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        match from_slice::<QueryRequest<Empty>>(bin_request) {
+            Ok(QueryRequest::Stargate { path, data }) if path == "/osmosis.gamm.v1beta1.Query/SpotPrice" => {
+                let request = QuerySpotPriceRequest::decode(data.as_slice()).unwrap();
+                let held = |denom: &str| self.pool.iter().find(|coin| coin.denom == denom).unwrap().amount;
+                let price = Decimal::from_ratio(held(&request.token_in_denom), held(&request.token_out_denom));
+                let response = QuerySpotPriceResponse {
+                    spot_price: price.to_string(),
+                };
+                SystemResult::Ok(ContractResult::Ok(response.encode_to_vec().into()))
+            }
+            _ => self.base.raw_query(bin_request),
+        }
+    }
+}
+
+/// Mock dependencies of a contract holding `balance` next to a pool holding `pool`
+pub fn mock_dependencies_with_pool(balance: &[Coin], pool: Vec<Coin>) -> OwnedDeps<MockStorage, MockApi, PoolQuerier> {
+    OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier: PoolQuerier {
+            base: MockQuerier::new(&[(MOCK_CONTRACT_ADDR, balance)]),
+            pool,
+        },
+        custom_query_type: PhantomData,
+    }
+}
@@ -0,0 +1,321 @@
+mod common;
+
+use blazarbit_protocol::ack::Ack;
+use blazarbit_protocol::asset::Asset;
+use blazarbit_protocol::contract::{execute, instantiate, query};
+use blazarbit_protocol::ibc::ibc_packet_receive;
+use blazarbit_protocol::msg::{ExecuteMsg, IbcExecuteMsg, InstantiateMsg, QueryMsg};
+use blazarbit_protocol::state::Fee;
+use common::*;
+use cosmwasm_std::testing::{mock_dependencies, mock_dependencies_with_balance, mock_env, mock_ibc_packet_recv, mock_info, MockApi, MockQuerier, MockStorage};
+use cosmwasm_std::{coins, from_binary, to_binary, BankMsg, Coin, CosmosMsg, IbcMsg, OwnedDeps, Uint128, WasmMsg};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw20_ics20::msg::TransferMsg;
+
+const CHANNEL: &str = "channel-7";
+const REMOTE: &str = "osmo1remote";
+
+fn setup_ibc(contract_balance: &[Coin]) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    let mut deps = mock_dependencies_with_balance(contract_balance);
+    let msg = InstantiateMsg {
+        allowed_channels: vec![CHANNEL.to_string()],
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+    deps
+}
+
+fn deposit(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, sender: &str, funds: &[Coin]) {
+    let msg = ExecuteMsg::DepositRemote {
+        channel: CHANNEL.to_string(),
+        sender: sender.to_string(),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(ALICE, funds), msg).unwrap();
+}
+
+/// Delivers a hop packet from `sender`, returns the error of its ack if it failed
+fn receive(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, sender: Option<&str>, commands: Vec<ExecuteMsg>, funds: Vec<Coin>) -> Option<String> {
+    let steps = commands.len().min(1);
+    let packet = IbcExecuteMsg::IbcContractHop {
+        commands,
+        funds,
+        sender: sender.map(String::from),
+        origin: None,
+    };
+    let msg = mock_ibc_packet_recv(CHANNEL, &packet).unwrap();
+    let response = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+    match from_binary(&response.acknowledgement).unwrap() {
+        Ack::Result(_) => {
+            assert_eq!(response.messages.len(), steps);
+            None
+        }
+        Ack::Error(err) => {
+            assert!(response.messages.is_empty());
+            Some(err)
+        }
+    }
+}
+
+fn remote_balance(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, sender: &str) -> Vec<Asset> {
+    let query_msg = QueryMsg::RemoteBalance {
+        channel: CHANNEL.to_string(),
+        sender: sender.to_string(),
+    };
+    from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+}
+
+#[test]
+fn packet_spends_the_remote_account_of_its_sender() {
+    let mut deps = setup_ibc(&coins(300, "uosmo"));
+    deposit(&mut deps, REMOTE, &coins(300, "uosmo"));
+
+    assert_eq!(receive(&mut deps, Some(REMOTE), vec![transfer(BOB)], coins(100, "uosmo")), None);
+    assert_eq!(remote_balance(&deps, REMOTE), vec![Asset::native("uosmo", 200u128)]);
+}
+
+#[test]
+fn packet_cannot_spend_what_its_sender_did_not_deposit() {
+    let mut deps = setup_ibc(&coins(300, "uosmo"));
+    deposit(&mut deps, REMOTE, &coins(300, "uosmo"));
+
+    let err = receive(&mut deps, Some("osmo1other"), vec![transfer(BOB)], coins(100, "uosmo")).unwrap();
+    assert_eq!(err, "100uosmo is more than what is available to spend");
+    let err = receive(&mut deps, Some(REMOTE), vec![transfer(BOB)], coins(301, "uosmo")).unwrap();
+    assert_eq!(err, "301uosmo is more than what is available to spend");
+    let err = receive(&mut deps, None, vec![transfer(BOB)], coins(100, "uosmo")).unwrap();
+    assert_eq!(err, "nobody to run the IBC contract hop for on the counterparty");
+
+    assert_eq!(remote_balance(&deps, REMOTE), vec![Asset::native("uosmo", 300u128)]);
+}
+
+#[test]
+fn what_a_packet_leaves_goes_back_to_its_sender() {
+    let mut deps = setup_ibc(&coins(300, "uosmo"));
+    deposit(&mut deps, REMOTE, &coins(300, "uosmo"));
+
+    assert_eq!(receive(&mut deps, Some(REMOTE), vec![], coins(100, "uosmo")), None);
+    assert_eq!(remote_balance(&deps, REMOTE), vec![Asset::native("uosmo", 300u128)]);
+}
+
+#[test]
+fn deposit_needs_an_allowlisted_channel() {
+    let mut deps = setup_ibc(&[]);
+    let msg = ExecuteMsg::DepositRemote {
+        channel: "channel-1".to_string(),
+        sender: REMOTE.to_string(),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(5, "uosmo")), msg).unwrap_err();
+    assert_eq!(err.to_string(), "IBC channel channel-1 is not allowlisted");
+}
+
+#[test]
+fn packet_size_counts_the_sender_of_the_packet_sent() {
+    let sender = format!("osmo1{}", "x".repeat(200));
+    let commands = vec![transfer(BOB)];
+    let sent = to_binary(&IbcExecuteMsg::IbcContractHop {
+        commands: commands.clone(),
+        funds: vec![],
+        sender: Some(sender.clone()),
+        origin: None,
+    })
+    .unwrap();
+
+    let mut deps = mock_dependencies_with_balance(&[]);
+    let msg = InstantiateMsg {
+        allowed_channels: vec![CHANNEL.to_string()],
+        max_packet_size: Some(sent.len() as u64 - 1),
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+
+    // without the sender the packet fits, with it the packet is one byte too large
+    let hop = ExecuteMsg::IbcContractHop {
+        channel: CHANNEL.to_string(),
+        commands,
+        remote_funds: vec![],
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(&sender, &[]), hop).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("IBC packet is {} bytes, at most {} are allowed", sent.len(), sent.len() - 1)
+    );
+}
+
+#[test]
+fn ibc_hop_refuses_attached_funds() {
+    let mut deps = mock_dependencies_with_balance(&[]);
+    let msg = InstantiateMsg {
+        allowed_channels: vec![CHANNEL.to_string()],
+        fee: Some(Fee::Bps(100)),
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+
+    // the packet carries none of them, they would only be charged a fee
+    let hop = ExecuteMsg::IbcContractHop {
+        channel: CHANNEL.to_string(),
+        commands: vec![transfer(BOB)],
+        remote_funds: coins(100, "uosmo"),
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(100, "uosmo")), hop.clone()).unwrap_err();
+    assert!(err.to_string().starts_with("funds mismatch: expected no funds"));
+
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &[]), hop).unwrap();
+    assert_eq!(response.messages.len(), 1);
+    assert!(!response.attributes.iter().any(|attribute| attribute.key == "fee"));
+}
+
+#[test]
+fn packet_whose_pipeline_cannot_start_changes_nothing() {
+    let mut deps = mock_dependencies_with_balance(&coins(300, "uosmo"));
+    let msg = InstantiateMsg {
+        allowed_channels: vec![CHANNEL.to_string()],
+        fee: Some(Fee::Bps(100)),
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+    deposit(&mut deps, REMOTE, &coins(300, "uosmo"));
+
+    // the first step asks for more than the pipeline gets once the fee is taken
+    let overspend = ExecuteMsg::Transfer {
+        address: BOB.to_string(),
+        asset: Some(Asset::native("uosmo", 100u128)),
+        referrer: None,
+    };
+    let err = receive(&mut deps, Some(REMOTE), vec![overspend], coins(100, "uosmo")).unwrap();
+    assert_eq!(err, "100uosmo is more than what is available to spend");
+
+    assert_eq!(remote_balance(&deps, REMOTE), vec![Asset::native("uosmo", 300u128)]);
+    let fees: Vec<Asset> = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::AccruedFees {}).unwrap()).unwrap();
+    assert!(fees.is_empty());
+    assert!(query(deps.as_ref(), mock_env(), QueryMsg::Pipeline { id: 1 }).is_err());
+}
+
+#[test]
+fn remote_sender_withdraws_to_the_recipient_it_names() {
+    let mut deps = setup_ibc(&coins(300, "uosmo"));
+    deposit(&mut deps, REMOTE, &coins(300, "uosmo"));
+
+    let withdraw = |sender: &str, amount: u128| IbcExecuteMsg::WithdrawRemote {
+        funds: coins(amount, "uosmo"),
+        sender: sender.to_string(),
+        recipient: ALICE.to_string(),
+    };
+    // nobody else can take from the account of the sender
+    let msg = mock_ibc_packet_recv(CHANNEL, &withdraw("osmo1other", 100)).unwrap();
+    let response = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+    assert!(matches!(from_binary(&response.acknowledgement).unwrap(), Ack::Error(_)));
+
+    let msg = mock_ibc_packet_recv(CHANNEL, &withdraw(REMOTE, 100)).unwrap();
+    let response = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+    assert!(matches!(from_binary(&response.acknowledgement).unwrap(), Ack::Result(_)));
+    assert_eq!(
+        response.messages[0].msg,
+        BankMsg::Send {
+            to_address: ALICE.to_string(),
+            amount: coins(100, "uosmo"),
+        }
+        .into()
+    );
+    assert_eq!(remote_balance(&deps, REMOTE), vec![Asset::native("uosmo", 200u128)]);
+}
+
+#[test]
+fn withdraw_remote_sends_the_packet_for_its_sender() {
+    let mut deps = setup_ibc(&[]);
+    let msg = ExecuteMsg::WithdrawRemote {
+        channel: CHANNEL.to_string(),
+        funds: coins(100, "uosmo"),
+        recipient: BOB.to_string(),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(5, "uosmo")), msg.clone()).unwrap_err();
+    assert!(err.to_string().starts_with("funds mismatch: expected no funds"));
+
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &[]), msg).unwrap();
+    match &response.messages[0].msg {
+        CosmosMsg::Ibc(IbcMsg::SendPacket { channel_id, data, .. }) => {
+            assert_eq!(channel_id, CHANNEL);
+            let packet: IbcExecuteMsg = from_binary(data).unwrap();
+            assert_eq!(
+                packet,
+                IbcExecuteMsg::WithdrawRemote {
+                    funds: coins(100, "uosmo"),
+                    sender: ALICE.to_string(),
+                    recipient: BOB.to_string(),
+                }
+            );
+        }
+        msg => panic!("unexpected message {:?}", msg),
+    }
+}
+
+#[test]
+fn cw20_ibc_transfer_goes_through_cw20_ics20() {
+    let cw20_transfer = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+        let transfer = ExecuteMsg::IbcTransfer {
+            channel_id: CHANNEL.to_string(),
+            address: REMOTE.to_string(),
+            asset: None,
+            referrer: None,
+        };
+        let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: ALICE.to_string(),
+            amount: Uint128::new(100),
+            msg: to_binary(&transfer).unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("token", &[]), receive)
+    };
+    let mut deps = setup_ibc(&[]);
+    let err = cw20_transfer(&mut deps).unwrap_err();
+    assert_eq!(err.to_string(), "cw20-ics20 contract is not configured");
+
+    let mut deps = mock_dependencies();
+    let msg = InstantiateMsg {
+        allowed_channels: vec![CHANNEL.to_string()],
+        cw20_ics20_contract: Some("ics20".to_string()),
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+    let response = cw20_transfer(&mut deps).unwrap();
+    // the token is sent to cw20-ics20, which reads the destination from the payload
+    let payload = TransferMsg {
+        channel: CHANNEL.to_string(),
+        remote_address: REMOTE.to_string(),
+        timeout: Some(3600),
+    };
+    let send = Cw20ExecuteMsg::Send {
+        contract: "ics20".to_string(),
+        amount: Uint128::new(100),
+        msg: to_binary(&payload).unwrap(),
+    };
+    let expected: CosmosMsg = WasmMsg::Execute {
+        contract_addr: "token".to_string(),
+        msg: to_binary(&send).unwrap(),
+        funds: vec![],
+    }
+    .into();
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(response.messages[0].msg, expected);
+}
+
+#[test]
+fn paused_inbound_packets_get_an_error_ack() {
+    let mut deps = setup_ibc(&coins(300, "uosmo"));
+    deposit(&mut deps, REMOTE, &coins(300, "uosmo"));
+    let pause = ExecuteMsg::SetPause {
+        transfers: None,
+        swaps: None,
+        nft: None,
+        hops: None,
+        ibc_receive: Some(true),
+        staking: None,
+        governance: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), pause).unwrap();
+
+    let err = receive(&mut deps, Some(REMOTE), vec![transfer(BOB)], coins(100, "uosmo"));
+    assert_eq!(err.as_deref(), Some("inbound IBC packets are paused"));
+    assert_eq!(remote_balance(&deps, REMOTE), vec![Asset::native("uosmo", 300u128)]);
+}
@@ -0,0 +1,29 @@
+use blazarbit_protocol::contract::migrate;
+use blazarbit_protocol::msg::MigrateMsg;
+use blazarbit_protocol::state::{CONFIG, PIPELINES};
+use cosmwasm_std::testing::{mock_dependencies, mock_env};
+use cosmwasm_std::Storage;
+
+#[test]
+fn migration_drops_the_pipeline_left_by_0_1_0() {
+    let mut deps = mock_dependencies();
+    cw2::set_contract_version(&mut deps.storage, "crates.io:blazarbit-protocol", "0.1.0").unwrap();
+    // an interrupted hop of 0.1.0, commands in reverse order
+    deps.storage.set(
+        b"commands_stack",
+        br#"[{"transfer":{"address":"bob"}},{"swap":{"pool_id":1,"token_out_denom":"uatom","token_out_min_amount":"1"}}]"#,
+    );
+    deps.storage.set(b"contract_address", br#""contract""#);
+
+    let msg = MigrateMsg {
+        admin: Some("admin".to_string()),
+    };
+    let response = migrate(deps.as_mut(), mock_env(), msg).unwrap();
+
+    let dropped = response.attributes.iter().find(|attr| attr.key == "dropped_commands").unwrap();
+    assert_eq!(dropped.value, "2");
+    assert_eq!(PIPELINES.may_load(&deps.storage).unwrap(), None);
+    assert_eq!(deps.storage.get(b"commands_stack"), None);
+    assert_eq!(deps.storage.get(b"contract_address"), None);
+    assert_eq!(CONFIG.load(&deps.storage).unwrap().admin, "admin");
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use blazarbit_protocol::contract::{execute, instantiate, reply};
+use blazarbit_protocol::lockup::LOCK_REPLY_ID;
+use blazarbit_protocol::msg::{ExecuteMsg, InstantiateMsg};
+use blazarbit_protocol::proto::{MsgLockTokensResponse, BEGIN_UNLOCKING_TYPE_URL, LOCK_TOKENS_TYPE_URL};
+use common::*;
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{coins, from_slice, BankMsg, Binary, Coin, CosmosMsg, OwnedDeps, Reply, Response, SubMsgResponse, SubMsgResult, Uint128};
+use cw_osmo_proto::osmosis::gamm::v1beta1::{MsgExitPool, MsgJoinPool, MsgJoinSwapExternAmountIn};
+use prost::Message;
+
+fn setup_pools() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    let mut deps = mock_dependencies();
+    let msg = InstantiateMsg {
+        allowed_pools: vec![1],
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+    deps
+}
+
+#[test]
+fn swap_minimum_keeps_its_string_form() {
+    let swap = |min: &str| format!(r#"{{"swap":{{"pool_id":1,"token_out_denom":"uatom","token_out_min_amount":{}}}}}"#, min);
+    match from_slice(swap(r#""1000""#).as_bytes()).unwrap() {
+        ExecuteMsg::Swap { token_out_min_amount, .. } => assert_eq!(token_out_min_amount, Uint128::new(1000)),
+        msg => panic!("unexpected {:?}", msg),
+    }
+    assert!(from_slice::<ExecuteMsg>(swap(r#""1e3""#).as_bytes()).is_err());
+    assert!(from_slice::<ExecuteMsg>(swap(r#""-1""#).as_bytes()).is_err());
+
+    // a minimum of nothing is refused before it reaches the pool
+    let mut deps = setup_pools();
+    let msg = from_slice(swap(r#""0""#).as_bytes()).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(100, "uosmo")), msg).unwrap_err();
+    assert_eq!(err.to_string(), "token_out_min_amount must be greater than zero");
+    let msg = from_slice(swap(r#""1000""#).as_bytes()).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(100, "uosmo")), msg).unwrap();
+}
+
+/// Type url and value of the only message of `response`
+fn stargate(response: &Response) -> (String, Binary) {
+    assert_eq!(response.messages.len(), 1);
+    match &response.messages[0].msg {
+        CosmosMsg::Stargate { type_url, value } => (type_url.clone(), value.clone()),
+        msg => panic!("unexpected {:?}", msg),
+    }
+}
+
+#[test]
+fn join_pool_asks_for_a_least_amount_of_shares() {
+    let mut deps = setup_pools();
+    let join = |share_out_amount: u128| ExecuteMsg::JoinPool {
+        pool_id: 1,
+        share_out_amount: Uint128::new(share_out_amount),
+        asset: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(100, "uosmo")), join(0)).unwrap_err();
+    assert_eq!(err.to_string(), "share_out_amount must be greater than zero");
+
+    // a single coin swaps its way in
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(100, "uosmo")), join(50)).unwrap();
+    let (type_url, value) = stargate(&response);
+    assert_eq!(type_url, "/osmosis.gamm.v1beta1.MsgJoinSwapExternAmountIn");
+    let msg = MsgJoinSwapExternAmountIn::decode(value.as_slice()).unwrap();
+    assert_eq!(msg.share_out_min_amount, "50");
+
+    // several coins join balanced, up to what was sent
+    let funds = [Coin::new(100, "uatom"), Coin::new(100, "uosmo")];
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &funds), join(50)).unwrap();
+    let (type_url, value) = stargate(&response);
+    assert_eq!(type_url, "/osmosis.gamm.v1beta1.MsgJoinPool");
+    let msg = MsgJoinPool::decode(value.as_slice()).unwrap();
+    assert_eq!(msg.share_out_amount, "50");
+    assert_eq!(msg.token_in_maxs.len(), 2);
+}
+
+#[test]
+fn exit_pool_returns_the_shares_of_its_pool() {
+    let mut deps = setup_pools();
+    let exit = ExecuteMsg::ExitPool {
+        pool_id: 1,
+        token_out_mins: coins(40, "uosmo"),
+        asset: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(100, "gamm/pool/2")), exit.clone()).unwrap_err();
+    assert_eq!(err.to_string(), "funds mismatch: expected gamm/pool/1, received 100gamm/pool/2");
+
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(100, "gamm/pool/1")), exit).unwrap();
+    let (type_url, value) = stargate(&response);
+    assert_eq!(type_url, "/osmosis.gamm.v1beta1.MsgExitPool");
+    let msg = MsgExitPool::decode(value.as_slice()).unwrap();
+    assert_eq!(msg.share_in_amount, "100");
+    assert_eq!(msg.token_out_mins.len(), 1);
+    assert_eq!(msg.token_out_mins[0].amount, "40");
+}
+
+#[test]
+fn lock_is_claimed_by_its_owner_once_unlocked() {
+    let mut deps = setup_pools();
+    let shares = coins(100, "gamm/pool/1");
+    let lock = ExecuteMsg::LockTokens {
+        duration: 100,
+        asset: None,
+        referrer: None,
+    };
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &shares), lock).unwrap();
+    assert_eq!(stargate(&response).0, LOCK_TOKENS_TYPE_URL);
+    // the chain answers with the id of the new lock
+    let data = MsgLockTokensResponse { id: 7 }.encode_to_vec();
+    let result = SubMsgResult::Ok(SubMsgResponse {
+        events: vec![],
+        data: Some(Binary::from(data)),
+    });
+    reply(deps.as_mut(), mock_env(), Reply { id: LOCK_REPLY_ID, result }).unwrap();
+
+    let unlock = ExecuteMsg::Unlock { lock_id: 7 };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(BOB, &[]), unlock.clone()).unwrap_err();
+    assert_eq!(err.to_string(), "Unauthorized");
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &[]), unlock.clone()).unwrap();
+    assert_eq!(stargate(&response).0, BEGIN_UNLOCKING_TYPE_URL);
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &[]), unlock).unwrap_err();
+    assert_eq!(err.to_string(), "lock 7 is already unlocking");
+
+    let claim = ExecuteMsg::ClaimUnlocked { lock_id: 7 };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &[]), claim.clone()).unwrap_err();
+    assert_eq!(err.to_string(), "lock 7 has not unlocked yet");
+
+    // the shares came back once the lock duration passed
+    deps.querier.update_balance(MOCK_CONTRACT_ADDR, shares.clone());
+    let mut env = mock_env();
+    env.block.height += 1;
+    env.block.time = env.block.time.plus_seconds(110);
+    let response = execute(deps.as_mut(), env, mock_info(ALICE, &[]), claim).unwrap();
+    let paid: CosmosMsg = BankMsg::Send {
+        to_address: ALICE.to_string(),
+        amount: shares,
+    }
+    .into();
+    assert_eq!(response.messages[0].msg, paid);
+}
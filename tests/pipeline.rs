@@ -0,0 +1,428 @@
+mod common;
+
+use blazarbit_protocol::asset::Asset;
+use blazarbit_protocol::contract::{execute, instantiate};
+use blazarbit_protocol::msg::{Condition, ExecuteMsg, InstantiateMsg, PipelinesResponse, QueryMsg, Referrer, SimulationResponse};
+use blazarbit_protocol::state::{Beneficiary, Fee, Pipeline, PipelineRecord, PipelineStatus, ReferralMode, StepStatus, Trigger, PIPELINES};
+use blazarbit_protocol::ContractError;
+use common::*;
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+use cosmwasm_std::{coins, to_binary, Addr, Binary, Uint128};
+use cw20::Cw20ReceiveMsg;
+
+#[test]
+fn hop_transfers_funds_received() {
+    let (mut app, contract) = setup();
+
+    let response = run(&mut app, ALICE, &contract, &hop(&contract, vec![transfer(BOB)]), &osmo(100)).unwrap();
+
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(100));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+    // a step emits its typed event, the pipeline ends with nothing left
+    assert!(response.events.iter().any(|event| event.ty == "wasm-blazarbit_transfer"));
+    assert!(response.events.iter().any(|event| event.ty == "wasm-blazarbit_pipeline_completed"));
+
+    let record: PipelineRecord = app.wrap().query_wasm_smart(&contract, &QueryMsg::Pipeline { id: 1 }).unwrap();
+    assert_eq!(record.status, PipelineStatus::Completed);
+    assert_eq!(record.steps.len(), 1);
+    assert_eq!(record.steps[0].status, StepStatus::Succeeded);
+}
+
+#[test]
+fn hop_refunds_what_the_pipeline_leaves() {
+    let (mut app, contract) = setup();
+
+    // an empty hop refunds right away, a hop whose commands spend nothing refunds at its end
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![]), &osmo(100)).unwrap();
+    let skipped = ExecuteMsg::If {
+        condition: Condition::PreviousStepFailed {},
+        then: vec![transfer(BOB)],
+        otherwise: vec![],
+    };
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![skipped]), &osmo(100)).unwrap();
+
+    assert_eq!(balance(&app, ALICE, "uosmo"), Uint128::new(10_000));
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::zero());
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+}
+
+#[test]
+fn pipeline_cannot_spend_escrowed_funds() {
+    let (mut app, contract) = setup();
+    let height = app.block_info().height;
+    let schedule = ExecuteMsg::SchedulePipeline {
+        commands: vec![transfer(BOB)],
+        trigger: Trigger::AtHeight(height + 100),
+    };
+    run(&mut app, ALICE, &contract, &schedule, &osmo(1000)).unwrap();
+
+    // naming an asset the pipeline was not given
+    let theft = ExecuteMsg::Transfer {
+        address: ATTACKER.to_string(),
+        asset: Some(Asset::native("uosmo", 1000u128)),
+        referrer: None,
+    };
+    let err = run(&mut app, ATTACKER, &contract, &hop(&contract, vec![theft]), &coins(5, "uatom")).unwrap_err();
+    assert_eq!(err, "1000uosmo is more than what is available to spend");
+
+    // taking everything only takes what was sent
+    run(&mut app, ATTACKER, &contract, &hop(&contract, vec![transfer(ATTACKER)]), &coins(5, "uatom")).unwrap();
+    assert_eq!(balance(&app, ATTACKER, "uosmo"), Uint128::new(10_000));
+    assert_eq!(balance(&app, ATTACKER, "uatom"), Uint128::new(10_000));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::new(1000));
+
+    run(&mut app, ALICE, &contract, &ExecuteMsg::CancelScheduled { id: 1 }, &[]).unwrap();
+    assert_eq!(balance(&app, ALICE, "uosmo"), Uint128::new(10_000));
+}
+
+#[test]
+fn pipeline_fees_stay_with_the_treasury() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        fee: Some(Fee::Bps(100)),
+        ..instantiate_msg()
+    });
+
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![transfer(BOB)]), &osmo(1000)).unwrap();
+    // the hop and the transfer are charged one after the other
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(981));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::new(19));
+
+    // a later pipeline only moves its own funds
+    run(&mut app, ATTACKER, &contract, &hop(&contract, vec![transfer(ATTACKER)]), &osmo(100)).unwrap();
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::new(20));
+
+    let withdraw = ExecuteMsg::WithdrawFees {
+        asset_info: None,
+        recipient: None,
+    };
+    run(&mut app, ADMIN, &contract, &withdraw, &[]).unwrap();
+    assert_eq!(balance(&app, ADMIN, "uosmo"), Uint128::new(20));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+}
+
+#[test]
+fn nested_hop_leaves_its_rest_to_the_outer_pipeline() {
+    let (mut app, contract) = setup();
+    let part = ExecuteMsg::Transfer {
+        address: BOB.to_string(),
+        asset: Some(Asset::native("uosmo", 40u128)),
+        referrer: None,
+    };
+
+    let outer = hop(&contract, vec![hop(&contract, vec![part]), transfer(KEEPER)]);
+    run(&mut app, ALICE, &contract, &outer, &osmo(100)).unwrap();
+
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(40));
+    assert_eq!(balance(&app, KEEPER, "uosmo"), Uint128::new(60));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+}
+
+#[test]
+fn cw20_receive_runs_as_a_step_only_for_the_token_handed_to_it() {
+    let mut deps = mock_dependencies();
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        allowed_type_urls: vec!["/cosmos.bank.v1beta1.MsgSend".to_string()],
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), env.clone(), mock_info(ADMIN, &[]), msg).unwrap();
+    // a step of a running pipeline was handed 10 of `token`
+    let running = Pipeline {
+        id: 1,
+        contract_addr: env.contract.address.clone(),
+        commands: vec![],
+        funds: vec![],
+        balances_before: vec![],
+        reserved_before: vec![],
+        beneficiary: Beneficiary::Pipeline,
+        previous_step_failed: false,
+        step_input: vec![Asset::cw20(Addr::unchecked("token"), 10u128)],
+    };
+    PIPELINES.save(deps.as_mut().storage, &vec![running]).unwrap();
+
+    let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: env.contract.address.to_string(),
+        amount: Uint128::new(10),
+        msg: to_binary(&ExecuteMsg::StargateMsg {
+            type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(),
+            value: Binary::default(),
+        })
+        .unwrap(),
+    });
+    // any other token naming the contract as its sender runs for the contract as an account
+    let err = execute(deps.as_mut(), env.clone(), mock_info("fake", &[]), receive.clone()).unwrap_err();
+    assert_eq!(err.to_string(), "StargateMsg can only run as part of a pipeline");
+
+    // the token of the step gets past the pipeline check and only fails for its funds
+    let err = execute(deps.as_mut(), env, mock_info("token", &[]), receive).unwrap_err();
+    assert!(matches!(err, ContractError::FundsMismatch { .. }));
+}
+
+#[test]
+fn command_limit_counts_nested_commands() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        max_commands: Some(3),
+        ..instantiate_msg()
+    });
+
+    // no list is longer than two, the tree holds four
+    let nested = hop(&contract, vec![hop(&contract, vec![transfer(BOB), transfer(BOB)]), transfer(BOB)]);
+    let err = run(&mut app, ALICE, &contract, &nested, &osmo(100)).unwrap_err();
+    assert_eq!(err, "pipeline holds 4 commands, at most 3 are allowed");
+
+    let nested = hop(&contract, vec![hop(&contract, vec![transfer(BOB)]), transfer(BOB)]);
+    run(&mut app, ALICE, &contract, &nested, &osmo(100)).unwrap();
+}
+
+#[test]
+fn if_branches_do_not_count_as_depth() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        max_depth: Some(1),
+        ..instantiate_msg()
+    });
+
+    let nested_if = ExecuteMsg::If {
+        condition: Condition::PreviousStepFailed {},
+        then: vec![],
+        otherwise: vec![ExecuteMsg::If {
+            condition: Condition::PreviousStepFailed {},
+            then: vec![],
+            otherwise: vec![transfer(BOB)],
+        }],
+    };
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![nested_if]), &osmo(100)).unwrap();
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(100));
+}
+
+#[test]
+fn if_through_cw20_receive_is_pipeline_only() {
+    let mut deps = mock_dependencies();
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), instantiate_msg()).unwrap();
+
+    let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: ALICE.to_string(),
+        amount: Uint128::new(10),
+        msg: to_binary(&ExecuteMsg::If {
+            condition: Condition::PreviousStepFailed {},
+            then: vec![transfer(BOB)],
+            otherwise: vec![],
+        })
+        .unwrap(),
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), receive).unwrap_err();
+    assert_eq!(err.to_string(), "If can only run as part of a pipeline");
+}
+
+#[test]
+fn transfer_pays_out_native_and_cw20_assets_alike() {
+    let mut deps = mock_dependencies();
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), instantiate_msg()).unwrap();
+    let token = Addr::unchecked("token");
+    let cw20_transfer = |asset: Asset| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: ALICE.to_string(),
+            amount: Uint128::new(40),
+            msg: to_binary(&ExecuteMsg::Transfer {
+                address: BOB.to_string(),
+                asset: Some(asset),
+                referrer: None,
+            })
+            .unwrap(),
+        })
+    };
+
+    let response = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(40, "uosmo")), transfer(BOB)).unwrap();
+    assert_eq!(response.messages[0].msg, Asset::native("uosmo", 40u128).transfer_msg(BOB).unwrap());
+    let received = Asset::cw20(token.clone(), 40u128);
+    let response = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), cw20_transfer(received.clone())).unwrap();
+    assert_eq!(response.messages[0].msg, received.transfer_msg(BOB).unwrap());
+
+    // the asset named has to be the one received
+    let err = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), cw20_transfer(Asset::cw20(token, 30u128))).unwrap_err();
+    assert_eq!(err.to_string(), "funds mismatch: expected 30token, received 40token");
+}
+
+fn referred_transfer(bps: u16) -> ExecuteMsg {
+    ExecuteMsg::Transfer {
+        address: BOB.to_string(),
+        asset: None,
+        referrer: Some(Referrer {
+            address: KEEPER.to_string(),
+            bps,
+        }),
+    }
+}
+
+#[test]
+fn referrer_claims_its_share_of_the_fee() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        fee: Some(Fee::Bps(100)),
+        max_referral_bps: 5000,
+        ..instantiate_msg()
+    });
+    let err = run(&mut app, ALICE, &contract, &referred_transfer(6000), &osmo(1000)).unwrap_err();
+    assert_eq!(err, "referral share of 6000 bps is above the 5000 bps maximum");
+
+    // half of the 10 fee goes to the referrer, the user pays no more than without one
+    run(&mut app, ALICE, &contract, &referred_transfer(5000), &osmo(1000)).unwrap();
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(990));
+    let query = QueryMsg::ReferralBalance {
+        address: KEEPER.to_string(),
+    };
+    let credited: Vec<Asset> = app.wrap().query_wasm_smart(&contract, &query).unwrap();
+    assert_eq!(credited, vec![Asset::native("uosmo", 5u128)]);
+
+    run(&mut app, KEEPER, &contract, &ExecuteMsg::ClaimReferralFees {}, &[]).unwrap();
+    assert_eq!(balance(&app, KEEPER, "uosmo"), Uint128::new(5));
+    let credited: Vec<Asset> = app.wrap().query_wasm_smart(&contract, &query).unwrap();
+    assert!(credited.is_empty());
+}
+
+#[test]
+fn referrer_share_on_top_is_paid_by_the_user() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        fee: Some(Fee::Bps(100)),
+        max_referral_bps: 100,
+        referral_mode: ReferralMode::OnTop,
+        ..instantiate_msg()
+    });
+
+    // the fee takes 10, the referrer 1% of the 990 left
+    run(&mut app, ALICE, &contract, &referred_transfer(100), &osmo(1000)).unwrap();
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(981));
+    run(&mut app, KEEPER, &contract, &ExecuteMsg::ClaimReferralFees {}, &[]).unwrap();
+    assert_eq!(balance(&app, KEEPER, "uosmo"), Uint128::new(9));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::new(10));
+}
+
+#[test]
+fn allowlists_refuse_unknown_targets_channels_and_pools() {
+    let (mut app, contract) = setup();
+
+    let elsewhere = ExecuteMsg::ContractHop {
+        contract_addr: ATTACKER.to_string(),
+        commands: vec![],
+        referrer: None,
+    };
+    let err = run(&mut app, ALICE, &contract, &elsewhere, &osmo(100)).unwrap_err();
+    assert_eq!(err, "hop target attacker is not allowlisted");
+
+    let ibc_hop = ExecuteMsg::IbcContractHop {
+        channel: "channel-9".to_string(),
+        commands: vec![transfer(BOB)],
+        remote_funds: vec![],
+        referrer: None,
+    };
+    let err = run(&mut app, ALICE, &contract, &ibc_hop, &[]).unwrap_err();
+    assert_eq!(err, "IBC channel channel-9 is not allowlisted");
+
+    let swap = ExecuteMsg::Swap {
+        pool_id: 7,
+        token_out_denom: "uatom".to_string(),
+        token_out_min_amount: Uint128::new(1),
+        asset: None,
+        referrer: None,
+    };
+    let err = run(&mut app, ALICE, &contract, &hop(&contract, vec![swap]), &osmo(100)).unwrap_err();
+    assert_eq!(err, "pool 7 is not allowlisted");
+    assert_eq!(balance(&app, ALICE, "uosmo"), Uint128::new(10_000));
+}
+
+#[test]
+fn simulation_walks_the_steps_without_running_them() {
+    let (app, contract) = setup_with(InstantiateMsg {
+        fee: Some(Fee::Bps(100)),
+        ..instantiate_msg()
+    });
+    let query = |commands: Vec<ExecuteMsg>| -> SimulationResponse {
+        let msg = QueryMsg::SimulatePipeline {
+            commands,
+            funds: vec![Asset::native("uosmo", 1000u128)],
+        };
+        app.wrap().query_wasm_smart(&contract, &msg).unwrap()
+    };
+    let simulation = query(vec![transfer(BOB)]);
+    assert_eq!(simulation.error, None);
+    assert_eq!(simulation.steps.len(), 1);
+    let step = &simulation.steps[0];
+    assert_eq!(step.input, vec![Asset::native("uosmo", 1000u128)]);
+    assert_eq!(step.fee, vec![Asset::native("uosmo", 10u128)]);
+    assert!(step.output.is_empty());
+    assert!(simulation.funds_left.is_empty());
+
+    // a failed check is reported rather than returned as a query error
+    let ibc_hop = ExecuteMsg::IbcContractHop {
+        channel: "channel-9".to_string(),
+        commands: vec![],
+        remote_funds: vec![],
+        referrer: None,
+    };
+    let simulation = query(vec![transfer(BOB), ibc_hop]);
+    assert_eq!(simulation.error.as_deref(), Some("IBC channel channel-9 is not allowlisted"));
+    assert!(simulation.steps.is_empty());
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::zero());
+}
+
+#[test]
+fn history_pages_through_the_pipelines_of_a_sender() {
+    let (mut app, contract) = setup();
+    for sender in [ALICE, ALICE, ATTACKER, ALICE] {
+        run(&mut app, sender, &contract, &hop(&contract, vec![transfer(BOB)]), &osmo(100)).unwrap();
+    }
+
+    let page = |start_after: Option<u64>| -> Vec<u64> {
+        let msg = QueryMsg::PipelinesBySender {
+            sender: ALICE.to_string(),
+            start_after,
+            limit: Some(2),
+        };
+        let response: PipelinesResponse = app.wrap().query_wasm_smart(&contract, &msg).unwrap();
+        response.pipelines.iter().map(|record| record.id).collect()
+    };
+    assert_eq!(page(None), vec![1, 2]);
+    assert_eq!(page(Some(2)), vec![4]);
+
+    let record: PipelineRecord = app.wrap().query_wasm_smart(&contract, &QueryMsg::Pipeline { id: 3 }).unwrap();
+    assert_eq!(record.sender, Some(Addr::unchecked(ATTACKER)));
+    assert_eq!(record.funds, vec![Asset::native("uosmo", 100u128)]);
+    assert_eq!(record.steps[0].input, vec![Asset::native("uosmo", 100u128)]);
+    assert_eq!(record.finished_at, Some(app.block_info().time));
+}
+
+#[test]
+fn failed_step_is_undone_and_the_if_picks_the_fallback() {
+    let (mut app, contract) = setup();
+    // the test chain has no staking, the delegation fails once the step runs
+    let failing = ExecuteMsg::Delegate {
+        validator: "validator".to_string(),
+        asset: None,
+        referrer: None,
+    };
+    let fallback = ExecuteMsg::If {
+        condition: Condition::PreviousStepFailed {},
+        then: vec![transfer(BOB)],
+        otherwise: vec![transfer(ATTACKER)],
+    };
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![failing, fallback]), &osmo(100)).unwrap();
+
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(100));
+    assert_eq!(balance(&app, ATTACKER, "uosmo"), Uint128::new(10_000));
+    let record: PipelineRecord = app.wrap().query_wasm_smart(&contract, &QueryMsg::Pipeline { id: 1 }).unwrap();
+    assert_eq!(record.steps[0].status, StepStatus::Failed);
+    assert_eq!(record.steps[1].status, StepStatus::Succeeded);
+}
+
+#[test]
+fn if_on_a_balance_runs_one_branch() {
+    let (mut app, contract) = setup();
+    let split = |threshold: u128| ExecuteMsg::If {
+        condition: Condition::BalanceAtLeast(Asset::native("uosmo", threshold)),
+        then: vec![transfer(BOB)],
+        otherwise: vec![transfer(KEEPER)],
+    };
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![split(100)]), &osmo(100)).unwrap();
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![split(101)]), &osmo(100)).unwrap();
+
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(100));
+    assert_eq!(balance(&app, KEEPER, "uosmo"), Uint128::new(100));
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use blazarbit_protocol::contract::{execute, instantiate, query};
+use blazarbit_protocol::msg::{Condition, ExecuteMsg, InstantiateMsg, QueryMsg};
+use blazarbit_protocol::asset::Asset;
+use blazarbit_protocol::state::{DcaDestination, Position, Receipt, SwapRoute, Trigger};
+use common::*;
+use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{coins, from_binary, BankMsg, CosmosMsg, Decimal, Uint128};
+
+#[test]
+fn holder_redeems_the_proceeds_of_a_settled_position() {
+    let mut deps = mock_dependencies_with_balance(&coins(1000, "uosmo"));
+    let env = mock_env();
+    instantiate(deps.as_mut(), env.clone(), mock_info(ADMIN, &[]), instantiate_msg()).unwrap();
+
+    let schedule = ExecuteMsg::SchedulePipeline {
+        commands: vec![],
+        trigger: Trigger::AtHeight(env.block.height),
+    };
+    execute(deps.as_mut(), env.clone(), mock_info(ALICE, &coins(1000, "uosmo")), schedule).unwrap();
+    let mint = ExecuteMsg::MintReceipt {
+        position: Position::Scheduled(1),
+    };
+    execute(deps.as_mut(), env.clone(), mock_info(ALICE, &[]), mint).unwrap();
+
+    // the run pays the receipt rather than the owner who sold it
+    let response = execute(deps.as_mut(), env.clone(), mock_info(KEEPER, &[]), ExecuteMsg::ExecuteScheduled { id: 1 }).unwrap();
+    assert!(response.messages.is_empty());
+    let receipt: Receipt = from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Receipt { position: Position::Scheduled(1) }).unwrap()).unwrap();
+    assert_eq!(receipt.proceeds, vec![Asset::native("uosmo", 1000u128)]);
+
+    let token = format!("factory/{}/scheduled-1", MOCK_CONTRACT_ADDR);
+    let response = execute(deps.as_mut(), env.clone(), mock_info(BOB, &coins(1, &token)), ExecuteMsg::RedeemReceipt {}).unwrap();
+    let payout = CosmosMsg::Bank(BankMsg::Send {
+        to_address: BOB.to_string(),
+        amount: coins(1000, "uosmo"),
+    });
+    assert!(response.messages.iter().any(|msg| msg.msg == payout));
+    let settled = response.events[0].attributes.iter().find(|attr| attr.key == "settled").unwrap();
+    assert_eq!(settled.value, "true");
+
+    // the burnt token cannot pay out twice
+    execute(deps.as_mut(), env.clone(), mock_info(BOB, &coins(1, &token)), ExecuteMsg::RedeemReceipt {}).unwrap_err();
+    assert!(query(deps.as_ref(), env, QueryMsg::Scheduled { id: 1 }).is_err());
+}
+
+#[test]
+fn holder_becomes_owner_of_a_live_position() {
+    let mut deps = mock_dependencies_with_balance(&coins(1000, "uosmo"));
+    let env = mock_env();
+    instantiate(deps.as_mut(), env.clone(), mock_info(ADMIN, &[]), instantiate_msg()).unwrap();
+
+    let schedule = ExecuteMsg::SchedulePipeline {
+        commands: vec![],
+        trigger: Trigger::AtHeight(env.block.height + 10),
+    };
+    execute(deps.as_mut(), env.clone(), mock_info(ALICE, &coins(1000, "uosmo")), schedule).unwrap();
+    let mint = ExecuteMsg::MintReceipt {
+        position: Position::Scheduled(1),
+    };
+    execute(deps.as_mut(), env.clone(), mock_info(ALICE, &[]), mint).unwrap();
+    execute(deps.as_mut(), env.clone(), mock_info(ALICE, &[]), ExecuteMsg::CancelScheduled { id: 1 }).unwrap_err();
+
+    let token = format!("factory/{}/scheduled-1", MOCK_CONTRACT_ADDR);
+    execute(deps.as_mut(), env.clone(), mock_info(BOB, &coins(1, &token)), ExecuteMsg::RedeemReceipt {}).unwrap();
+    let response = execute(deps.as_mut(), env, mock_info(BOB, &[]), ExecuteMsg::CancelScheduled { id: 1 }).unwrap();
+    let refund = CosmosMsg::Bank(BankMsg::Send {
+        to_address: BOB.to_string(),
+        amount: coins(1000, "uosmo"),
+    });
+    assert_eq!(response.messages[0].msg, refund);
+}
+
+#[test]
+fn positions_paying_a_fixed_recipient_get_no_receipt() {
+    let mut deps = mock_dependencies_with_balance(&[]);
+    let env = mock_env();
+    let msg = InstantiateMsg {
+        allowed_pools: vec![1],
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), env.clone(), mock_info(ADMIN, &[]), msg).unwrap();
+
+    // a transfer in any branch would pay Bob whoever holds the receipt
+    let schedule = ExecuteMsg::SchedulePipeline {
+        commands: vec![ExecuteMsg::If {
+            condition: Condition::PreviousStepFailed {},
+            then: vec![],
+            otherwise: vec![transfer(BOB)],
+        }],
+        trigger: Trigger::AtHeight(env.block.height + 10),
+    };
+    execute(deps.as_mut(), env.clone(), mock_info(ALICE, &coins(1000, "uosmo")), schedule).unwrap();
+    let mint = ExecuteMsg::MintReceipt {
+        position: Position::Scheduled(1),
+    };
+    let err = execute(deps.as_mut(), env.clone(), mock_info(ALICE, &[]), mint).unwrap_err();
+    assert_eq!(err.to_string(), "scheduled-1 pays its output to a fixed recipient, a receipt could not claim it");
+
+    let create = ExecuteMsg::CreateDca {
+        pool_routes: vec![SwapRoute {
+            pool_id: 1,
+            token_out_denom: "uatom".to_string(),
+        }],
+        amount_per_interval: Uint128::new(100),
+        interval_seconds: 60,
+        occurrences: 10,
+        destination: Some(DcaDestination::Address(BOB.to_string())),
+        min_price: Some(Decimal::percent(1)),
+    };
+    execute(deps.as_mut(), env.clone(), mock_info(ALICE, &coins(1000, "uosmo")), create).unwrap();
+    let mint = ExecuteMsg::MintReceipt {
+        position: Position::Dca(1),
+    };
+    let err = execute(deps.as_mut(), env, mock_info(ALICE, &[]), mint).unwrap_err();
+    assert_eq!(err.to_string(), "dca-1 pays its output to a fixed recipient, a receipt could not claim it");
+}
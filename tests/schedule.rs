@@ -0,0 +1,291 @@
+mod common;
+
+use blazarbit_protocol::asset::Asset;
+use blazarbit_protocol::contract::{execute, instantiate};
+use blazarbit_protocol::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use blazarbit_protocol::state::{Dca, Fee, SwapRoute, Trigger};
+use common::*;
+use cosmwasm_std::testing::{mock_env, mock_info};
+use cosmwasm_std::{coins, Coin, Decimal, Uint128};
+use cw20::Expiration;
+
+fn schedule(commands: Vec<ExecuteMsg>, height: u64) -> ExecuteMsg {
+    ExecuteMsg::SchedulePipeline {
+        commands,
+        trigger: Trigger::AtHeight(height),
+    }
+}
+
+#[test]
+fn keeper_runs_scheduled_pipeline_without_tip() {
+    let (mut app, contract) = setup();
+    let height = app.block_info().height;
+
+    run(&mut app, ALICE, &contract, &schedule(vec![transfer(BOB)], height + 5), &osmo(1000)).unwrap();
+    let err = run(&mut app, KEEPER, &contract, &ExecuteMsg::ExecuteScheduled { id: 1 }, &[]).unwrap_err();
+    assert!(err.contains("cannot run yet"), "{}", err);
+
+    app.update_block(|block| block.height += 5);
+    let response = run(&mut app, KEEPER, &contract, &ExecuteMsg::ExecuteScheduled { id: 1 }, &[]).unwrap();
+
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(1000));
+    assert_eq!(balance(&app, KEEPER, "uosmo"), Uint128::zero());
+    let executed = response
+        .events
+        .iter()
+        .find(|event| event.ty == "wasm-blazarbit_execute_scheduled")
+        .unwrap();
+    assert!(executed.attributes.iter().all(|attr| !attr.key.starts_with("tip_")));
+}
+
+#[test]
+fn owner_cancels_scheduled_pipeline() {
+    let (mut app, contract) = setup();
+    let height = app.block_info().height;
+
+    run(&mut app, ALICE, &contract, &schedule(vec![transfer(BOB)], height + 5), &osmo(1000)).unwrap();
+    run(&mut app, ATTACKER, &contract, &ExecuteMsg::CancelScheduled { id: 1 }, &[]).unwrap_err();
+    run(&mut app, ALICE, &contract, &ExecuteMsg::CancelScheduled { id: 1 }, &[]).unwrap();
+
+    assert_eq!(balance(&app, ALICE, "uosmo"), Uint128::new(10_000));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+}
+
+#[test]
+fn paused_commands_cannot_be_scheduled() {
+    let (mut app, contract) = setup();
+    let pause = ExecuteMsg::SetPause {
+        transfers: Some(true),
+        swaps: None,
+        nft: None,
+        hops: None,
+        ibc_receive: None,
+        staking: None,
+        governance: None,
+    };
+    run(&mut app, ADMIN, &contract, &pause, &[]).unwrap();
+
+    let height = app.block_info().height;
+    let err = run(&mut app, ALICE, &contract, &schedule(vec![transfer(BOB)], height + 5), &osmo(1000)).unwrap_err();
+    assert_eq!(err, "transfers are paused");
+    assert_eq!(balance(&app, ALICE, "uosmo"), Uint128::new(10_000));
+}
+
+fn flat_tip(denom: &str) -> Option<Fee> {
+    Some(Fee::Flat(Asset::native(denom, 10u128)))
+}
+
+#[test]
+fn schedule_must_cover_flat_keeper_tip() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        keeper_tip: flat_tip("uatom"),
+        ..instantiate_msg()
+    });
+    let height = app.block_info().height;
+
+    let err = run(&mut app, ALICE, &contract, &schedule(vec![transfer(BOB)], height + 5), &osmo(1000)).unwrap_err();
+    assert!(err.contains("does not cover"), "{}", err);
+}
+
+#[test]
+fn tip_changed_after_scheduling_is_skipped() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        keeper_tip: flat_tip("uosmo"),
+        ..instantiate_msg()
+    });
+    let height = app.block_info().height;
+    run(&mut app, ALICE, &contract, &schedule(vec![transfer(BOB)], height + 5), &osmo(1000)).unwrap();
+
+    let update = ExecuteMsg::UpdateConfig {
+        ibc_transfer_timeout: None,
+        ibc_hop_timeout: None,
+        fee: None,
+        command_fees: None,
+        allowed_targets: None,
+        cw20_ics20_contract: None,
+        max_referral_bps: None,
+        referral_mode: None,
+        max_depth: None,
+        max_commands: None,
+        max_packet_size: None,
+        keeper_tip: flat_tip("uatom"),
+        unbonding_period: None,
+        clear: vec![],
+    };
+    run(&mut app, ADMIN, &contract, &update, &[]).unwrap();
+
+    // the run cannot pay the new tip, it goes ahead without one
+    app.update_block(|block| block.height += 5);
+    run(&mut app, KEEPER, &contract, &ExecuteMsg::ExecuteScheduled { id: 1 }, &[]).unwrap();
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(1000));
+    assert_eq!(balance(&app, KEEPER, "uatom"), Uint128::zero());
+}
+
+#[test]
+fn dca_min_price_must_be_positive() {
+    let (mut app, contract) = setup();
+    let create = ExecuteMsg::CreateDca {
+        pool_routes: vec![SwapRoute {
+            pool_id: 1,
+            token_out_denom: "uatom".to_string(),
+        }],
+        amount_per_interval: Uint128::new(100),
+        interval_seconds: 60,
+        occurrences: 10,
+        destination: None,
+        min_price: Some(Decimal::zero()),
+    };
+    let err = run(&mut app, ALICE, &contract, &create, &osmo(1000)).unwrap_err();
+    assert_eq!(err, "min_price must be greater than zero");
+}
+
+#[test]
+fn dca_without_min_price_takes_any_price() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        allowed_pools: vec![1],
+        ..instantiate_msg()
+    });
+    let create = ExecuteMsg::CreateDca {
+        pool_routes: vec![SwapRoute {
+            pool_id: 1,
+            token_out_denom: "uatom".to_string(),
+        }],
+        amount_per_interval: Uint128::new(100),
+        interval_seconds: 60,
+        occurrences: 10,
+        destination: None,
+        min_price: None,
+    };
+    run(&mut app, ALICE, &contract, &create, &osmo(1000)).unwrap();
+    let dca: Dca = app.wrap().query_wasm_smart(&contract, &QueryMsg::Dca { id: 1 }).unwrap();
+    assert_eq!(dca.min_price, None);
+}
+
+#[test]
+fn paused_swaps_stop_new_dcas() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        allowed_pools: vec![1],
+        ..instantiate_msg()
+    });
+    let pause = ExecuteMsg::SetPause {
+        transfers: None,
+        swaps: Some(true),
+        nft: None,
+        hops: None,
+        ibc_receive: None,
+        staking: None,
+        governance: None,
+    };
+    run(&mut app, ADMIN, &contract, &pause, &[]).unwrap();
+
+    let create = ExecuteMsg::CreateDca {
+        pool_routes: vec![SwapRoute {
+            pool_id: 1,
+            token_out_denom: "uatom".to_string(),
+        }],
+        amount_per_interval: Uint128::new(100),
+        interval_seconds: 60,
+        occurrences: 10,
+        destination: None,
+        min_price: None,
+    };
+    let err = run(&mut app, ALICE, &contract, &create, &osmo(1000)).unwrap_err();
+    assert_eq!(err, "swaps are paused");
+}
+
+#[test]
+fn keeper_run_spends_only_its_own_escrow() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        keeper_tip: Some(Fee::Bps(100)),
+        ..instantiate_msg()
+    });
+    let height = app.block_info().height;
+    run(&mut app, ALICE, &contract, &schedule(vec![transfer(BOB)], height + 5), &osmo(1000)).unwrap();
+    run(&mut app, ATTACKER, &contract, &schedule(vec![transfer(ATTACKER)], height + 5), &osmo(100)).unwrap();
+
+    app.update_block(|block| block.height += 5);
+    run(&mut app, KEEPER, &contract, &ExecuteMsg::ExecuteScheduled { id: 2 }, &[]).unwrap();
+    assert_eq!(balance(&app, ATTACKER, "uosmo"), Uint128::new(9_999));
+    assert_eq!(balance(&app, KEEPER, "uosmo"), Uint128::new(1));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::new(1000));
+
+    run(&mut app, KEEPER, &contract, &ExecuteMsg::ExecuteScheduled { id: 1 }, &[]).unwrap();
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(990));
+    assert_eq!(balance(&app, KEEPER, "uosmo"), Uint128::new(11));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+}
+
+#[test]
+fn limit_order_price_is_the_output_per_offered_coin() {
+    // a uosmo buys 2 uatom in the pool
+    let mut deps = mock_dependencies_with_pool(&coins(100, "uosmo"), vec![Coin::new(1_000, "uosmo"), Coin::new(2_000, "uatom")]);
+    let msg = InstantiateMsg {
+        allowed_pools: vec![1],
+        ..instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+    let place = |min_price: u64| ExecuteMsg::PlaceLimitOrder {
+        pool_id: 1,
+        token_out_denom: "uatom".to_string(),
+        min_price: Decimal::from_ratio(min_price, 10u64),
+        expiry: Expiration::Never {},
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(50, "uosmo")), place(25)).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info(ALICE, &coins(50, "uosmo")), place(15)).unwrap();
+
+    let err = execute(deps.as_mut(), mock_env(), mock_info(KEEPER, &[]), ExecuteMsg::FillLimitOrder { id: 1 }).unwrap_err();
+    assert_eq!(err.to_string(), "limit order 1 asks for 2.5, the pool price is 2");
+    execute(deps.as_mut(), mock_env(), mock_info(KEEPER, &[]), ExecuteMsg::FillLimitOrder { id: 2 }).unwrap();
+}
+
+#[test]
+fn paused_swaps_stop_new_limit_orders() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        allowed_pools: vec![1],
+        ..instantiate_msg()
+    });
+    let pause = ExecuteMsg::SetPause {
+        transfers: None,
+        swaps: Some(true),
+        nft: None,
+        hops: None,
+        ibc_receive: None,
+        staking: None,
+        governance: None,
+    };
+    run(&mut app, ADMIN, &contract, &pause, &[]).unwrap();
+
+    let place = ExecuteMsg::PlaceLimitOrder {
+        pool_id: 1,
+        token_out_denom: "uatom".to_string(),
+        min_price: Decimal::one(),
+        expiry: Expiration::Never {},
+    };
+    let err = run(&mut app, ALICE, &contract, &place, &osmo(1000)).unwrap_err();
+    assert_eq!(err, "swaps are paused");
+}
+
+#[test]
+fn expired_limit_order_is_refunded_by_anyone() {
+    let (mut app, contract) = setup_with(InstantiateMsg {
+        allowed_pools: vec![1],
+        ..instantiate_msg()
+    });
+    let height = app.block_info().height;
+    let place = ExecuteMsg::PlaceLimitOrder {
+        pool_id: 1,
+        token_out_denom: "uatom".to_string(),
+        min_price: Decimal::one(),
+        expiry: Expiration::AtHeight(height + 5),
+    };
+    run(&mut app, ALICE, &contract, &place, &osmo(1000)).unwrap();
+    let err = run(&mut app, KEEPER, &contract, &ExecuteMsg::CancelLimitOrder { id: 1 }, &[]).unwrap_err();
+    assert_eq!(err, "Unauthorized");
+
+    app.update_block(|block| block.height += 5);
+    let err = run(&mut app, KEEPER, &contract, &ExecuteMsg::FillLimitOrder { id: 1 }, &[]).unwrap_err();
+    assert_eq!(err, "limit order 1 expired");
+    run(&mut app, KEEPER, &contract, &ExecuteMsg::CancelLimitOrder { id: 1 }, &[]).unwrap();
+    assert_eq!(balance(&app, ALICE, "uosmo"), Uint128::new(10_000));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+}
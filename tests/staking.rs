@@ -0,0 +1,232 @@
+mod common;
+
+use blazarbit_protocol::asset::Asset;
+use blazarbit_protocol::contract::{execute, instantiate};
+use blazarbit_protocol::msg::{ExecuteMsg, InstantiateMsg, WeightedVoteOption};
+use blazarbit_protocol::state::Fee;
+use blazarbit_protocol::ContractError;
+use common::*;
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{BankMsg, Coin, CosmosMsg, Decimal, Env, GovMsg, OwnedDeps, Response, StakingMsg, Uint128, VoteOption};
+
+const VALIDATOR: &str = "validator";
+const UNBONDING_PERIOD: u64 = 100;
+
+type Deps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+/// Contract bonding uosmo, `msg` instantiated by ADMIN
+fn setup_staking(msg: InstantiateMsg) -> Deps {
+    let mut deps = mock_dependencies();
+    deps.querier.update_staking("uosmo", &[], &[]);
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+    deps
+}
+
+fn staking_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        unbonding_period: Some(UNBONDING_PERIOD),
+        ..instantiate_msg()
+    }
+}
+
+fn delegate() -> ExecuteMsg {
+    ExecuteMsg::Delegate {
+        validator: VALIDATOR.to_string(),
+        asset: None,
+        referrer: None,
+    }
+}
+
+fn undelegate(amount: u128) -> ExecuteMsg {
+    ExecuteMsg::Undelegate {
+        validator: VALIDATOR.to_string(),
+        amount: Uint128::new(amount),
+    }
+}
+
+/// Env `blocks` blocks and `seconds` seconds after the mock one
+fn later(blocks: u64, seconds: u64) -> Env {
+    let mut env = mock_env();
+    env.block.height += blocks;
+    env.block.time = env.block.time.plus_seconds(seconds);
+    env
+}
+
+fn exec(deps: &mut Deps, env: Env, sender: &str, msg: ExecuteMsg, funds: &[Coin]) -> Result<Response, ContractError> {
+    execute(deps.as_mut(), env, mock_info(sender, funds), msg)
+}
+
+/// The chain paying back undelegated coins, the contract holds `amount` afterwards
+fn holds(deps: &mut Deps, amount: u128) {
+    deps.querier.update_balance(MOCK_CONTRACT_ADDR, osmo(amount));
+}
+
+fn paid(response: &Response, to: &str, amount: u128) -> bool {
+    response.messages.iter().any(|msg| {
+        msg.msg
+            == CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.to_string(),
+                amount: osmo(amount),
+            })
+    })
+}
+
+#[test]
+fn commands_spending_nothing_pay_no_flat_fee() {
+    let mut deps = setup_staking(InstantiateMsg {
+        fee: Some(Fee::Flat(Asset::native("uosmo", 10u128))),
+        ..staking_msg()
+    });
+    // 990 are staked once the fee is taken
+    let response = exec(&mut deps, mock_env(), ALICE, delegate(), &osmo(1_000)).unwrap();
+    assert!(response.messages.iter().any(|msg| msg.msg
+        == CosmosMsg::Staking(StakingMsg::Delegate {
+            validator: VALIDATOR.to_string(),
+            amount: Coin::new(990, "uosmo"),
+        })));
+
+    let redelegate = ExecuteMsg::Redelegate {
+        src_validator: VALIDATOR.to_string(),
+        dst_validator: "other".to_string(),
+        amount: Uint128::new(490),
+    };
+    exec(&mut deps, mock_env(), ALICE, redelegate, &[]).unwrap();
+    exec(&mut deps, mock_env(), ALICE, undelegate(500), &[]).unwrap();
+    // with no stake left on the validator there are no rewards to withdraw from it
+    let claim = ExecuteMsg::ClaimRewards {
+        validator: VALIDATOR.to_string(),
+    };
+    exec(&mut deps, mock_env(), ALICE, claim, &[]).unwrap();
+}
+
+#[test]
+fn paused_staking_stops_staking_commands() {
+    let mut deps = setup_staking(staking_msg());
+    let pause = ExecuteMsg::SetPause {
+        transfers: None,
+        swaps: None,
+        nft: None,
+        hops: None,
+        ibc_receive: None,
+        staking: Some(true),
+        governance: None,
+    };
+    exec(&mut deps, mock_env(), ADMIN, pause, &[]).unwrap();
+
+    let err = exec(&mut deps, mock_env(), ALICE, delegate(), &osmo(1_000)).unwrap_err();
+    assert_eq!(err.to_string(), "staking commands are paused");
+    let err = exec(&mut deps, mock_env(), ALICE, undelegate(500), &[]).unwrap_err();
+    assert_eq!(err.to_string(), "staking commands are paused");
+}
+
+#[test]
+fn unbonded_coins_are_kept_for_their_owner() {
+    let mut deps = setup_staking(staking_msg());
+    exec(&mut deps, mock_env(), ALICE, delegate(), &osmo(1_000)).unwrap();
+    exec(&mut deps, mock_env(), BOB, delegate(), &osmo(1_000)).unwrap();
+    exec(&mut deps, mock_env(), ALICE, undelegate(1_000), &[]).unwrap();
+    exec(&mut deps, later(1, 10), BOB, undelegate(1_000), &[]).unwrap();
+
+    // only the coins of Alice came back so far, Bob cannot claim them
+    holds(&mut deps, 1_000);
+    let env = later(2, UNBONDING_PERIOD + 10);
+    let err = exec(&mut deps, env.clone(), BOB, ExecuteMsg::ClaimUnbonded { id: 2 }, &[]).unwrap_err();
+    assert_eq!(err.to_string(), "unbonding 2 has not completed yet");
+    let response = exec(&mut deps, env, ALICE, ExecuteMsg::ClaimUnbonded { id: 1 }, &[]).unwrap();
+    assert!(paid(&response, ALICE, 1_000));
+
+    // Alice took hers, then the coins of Bob came back
+    holds(&mut deps, 1_000);
+    let response = exec(&mut deps, later(3, UNBONDING_PERIOD + 20), BOB, ExecuteMsg::ClaimUnbonded { id: 2 }, &[]).unwrap();
+    assert!(paid(&response, BOB, 1_000));
+}
+
+#[test]
+fn slashed_unbondings_share_what_came_back() {
+    let mut deps = setup_staking(staking_msg());
+    exec(&mut deps, mock_env(), ALICE, delegate(), &osmo(1_000)).unwrap();
+    exec(&mut deps, mock_env(), BOB, delegate(), &osmo(1_000)).unwrap();
+    exec(&mut deps, mock_env(), ALICE, undelegate(1_000), &[]).unwrap();
+    exec(&mut deps, later(1, 10), BOB, undelegate(1_000), &[]).unwrap();
+
+    // both unbondings are due, 100 of them were slashed
+    holds(&mut deps, 1_900);
+    let env = later(2, UNBONDING_PERIOD + 20);
+    // which one came back short only shows once a block passed since they were due
+    let err = exec(&mut deps, env.clone(), ALICE, ExecuteMsg::ClaimUnbonded { id: 1 }, &[]).unwrap_err();
+    assert_eq!(err.to_string(), "unbonding 1 has not completed yet");
+    exec(&mut deps, env, ALICE, transfer(ALICE), &osmo(1)).unwrap();
+
+    let env = later(3, UNBONDING_PERIOD + 25);
+    let response = exec(&mut deps, env.clone(), ALICE, ExecuteMsg::ClaimUnbonded { id: 1 }, &[]).unwrap();
+    assert!(paid(&response, ALICE, 950));
+    let response = exec(&mut deps, env, BOB, ExecuteMsg::ClaimUnbonded { id: 2 }, &[]).unwrap();
+    assert!(paid(&response, BOB, 950));
+}
+
+#[test]
+fn votes_pay_no_flat_fee() {
+    let mut deps = setup_staking(InstantiateMsg {
+        fee: Some(Fee::Flat(Asset::native("uosmo", 10u128))),
+        ..staking_msg()
+    });
+    // the vote gets past the fee and only fails for the missing stake
+    let vote = ExecuteMsg::Vote {
+        proposal_id: 1,
+        option: VoteOption::Yes,
+    };
+    let err = exec(&mut deps, mock_env(), ALICE, vote, &[]).unwrap_err();
+    assert_eq!(err.to_string(), "voting needs stake delegated through the contract");
+}
+
+#[test]
+fn a_new_vote_replaces_the_stake_of_the_old_one() {
+    let mut deps = mock_dependencies();
+    deps.querier.update_staking("uosmo", &[], &[]);
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), instantiate_msg()).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info(ALICE, &osmo(300)), delegate()).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info(BOB, &osmo(100)), delegate()).unwrap();
+
+    let mut vote = |voter: &str, option: VoteOption| {
+        let msg = ExecuteMsg::Vote { proposal_id: 1, option };
+        let response = execute(deps.as_mut(), mock_env(), mock_info(voter, &[]), msg).unwrap();
+        let cast = response.events[0]
+            .attributes
+            .iter()
+            .find(|attribute| attribute.key == "cast")
+            .unwrap();
+        (cast.value.clone(), response.messages[0].msg.clone())
+    };
+    assert_eq!(vote(ALICE, VoteOption::Yes).0, "yes:1");
+    assert_eq!(vote(BOB, VoteOption::No).0, "yes:0.75,no:0.25");
+    // the stake of Alice moves from yes to no
+    let (cast, msg) = vote(ALICE, VoteOption::No);
+    assert_eq!(cast, "no:1");
+    assert_eq!(
+        msg,
+        GovMsg::Vote {
+            proposal_id: 1,
+            vote: VoteOption::No,
+        }
+        .into()
+    );
+}
+
+#[test]
+fn a_vote_too_small_for_its_weights_is_refused() {
+    let mut deps = mock_dependencies();
+    deps.querier.update_staking("uosmo", &[], &[]);
+    instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), instantiate_msg()).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info(ALICE, &osmo(1)), delegate()).unwrap();
+
+    let half = Decimal::percent(50);
+    let vote = ExecuteMsg::VoteWeighted {
+        proposal_id: 1,
+        options: vec![
+            WeightedVoteOption { option: VoteOption::Yes, weight: half },
+            WeightedVoteOption { option: VoteOption::No, weight: half },
+        ],
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ALICE, &[]), vote).unwrap_err();
+    assert_eq!(err.to_string(), "invalid vote: no stake is behind the vote");
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use blazarbit_protocol::asset::Asset;
+use blazarbit_protocol::msg::{AmountSpec, ExecuteMsg, InstantiateMsg};
+use common::*;
+use cosmwasm_std::{to_binary, Addr, Empty, Response, Uint128};
+use cw_multi_test::{App, ContractWrapper, Executor};
+
+/// Contract that keeps whatever it is sent
+fn sink(app: &mut App) -> Addr {
+    let code = ContractWrapper::new(
+        |_, _, _, _: Empty| -> Result<Response, cosmwasm_std::StdError> { Ok(Response::new()) },
+        |_, _, _, _: Empty| -> Result<Response, cosmwasm_std::StdError> { Ok(Response::new()) },
+        |_, _, _: Empty| -> Result<cosmwasm_std::Binary, cosmwasm_std::StdError> { to_binary(&()) },
+    );
+    let code_id = app.store_code(Box::new(code));
+    app.instantiate_contract(code_id, Addr::unchecked(ADMIN), &Empty {}, &[], "sink", None)
+        .unwrap()
+}
+
+fn setup_wasm() -> (App, Addr, Addr) {
+    let (mut app, _) = setup();
+    let sink = sink(&mut app);
+    let code_id = app.store_code(contract());
+    let msg = InstantiateMsg {
+        allowed_contracts: vec![sink.to_string()],
+        ..instantiate_msg()
+    };
+    let contract = app
+        .instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], "blazarbit", None)
+        .unwrap();
+    (app, contract, sink)
+}
+
+fn wasm_execute(sink: &Addr, funds: AmountSpec) -> ExecuteMsg {
+    ExecuteMsg::WasmExecute {
+        contract_addr: sink.to_string(),
+        msg: to_binary(&Empty {}).unwrap(),
+        funds,
+        referrer: None,
+    }
+}
+
+#[test]
+fn account_sends_what_the_spec_selects() {
+    let (mut app, contract, sink) = setup_wasm();
+    let exact = AmountSpec::Exact(vec![Asset::native("uosmo", 50u128)]);
+
+    let err = run(&mut app, ALICE, &contract, &wasm_execute(&sink, exact.clone()), &osmo(100)).unwrap_err();
+    assert_eq!(err, "funds mismatch: expected 50uosmo, received 100uosmo");
+    let err = run(&mut app, ALICE, &contract, &wasm_execute(&sink, AmountSpec::None {}), &osmo(100)).unwrap_err();
+    assert_eq!(err, "funds mismatch: expected no funds, received 100uosmo");
+
+    run(&mut app, ALICE, &contract, &wasm_execute(&sink, exact), &osmo(50)).unwrap();
+    assert_eq!(balance(&app, sink.as_str(), "uosmo"), Uint128::new(50));
+    assert_eq!(balance(&app, contract.as_str(), "uosmo"), Uint128::zero());
+}
+
+#[test]
+fn pipeline_attaches_only_funds_it_owns() {
+    let (mut app, contract, sink) = setup_wasm();
+
+    let too_much = wasm_execute(&sink, AmountSpec::Exact(vec![Asset::native("uosmo", 150u128)]));
+    let err = run(&mut app, ALICE, &contract, &hop(&contract, vec![too_much]), &osmo(100)).unwrap_err();
+    assert_eq!(err, "150uosmo is more than what is available to spend");
+
+    let part = wasm_execute(&sink, AmountSpec::Exact(vec![Asset::native("uosmo", 40u128)]));
+    run(&mut app, ALICE, &contract, &hop(&contract, vec![part, transfer(BOB)]), &osmo(100)).unwrap();
+    assert_eq!(balance(&app, sink.as_str(), "uosmo"), Uint128::new(40));
+    assert_eq!(balance(&app, BOB, "uosmo"), Uint128::new(60));
+}